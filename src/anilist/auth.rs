@@ -1,75 +1,208 @@
+use crate::settings;
 use anyhow::{anyhow, Result};
-use log::debug;
+use log::{debug, warn};
 use oauth2::{prelude::SecretNewType, CsrfToken};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, Write},
-    net::TcpListener,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
 };
-use url::Url;
+use url::{form_urlencoded, Url};
 
-pub async fn auth() -> Result<String> {
-    let code;
+/// How the user logs in to AniList
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthFlow {
+    /// The code from the login is exchanged for a token by the token endpoint
+    Code,
+    /// AniList gives the token straight to the callback, no token endpoint needed
+    Implicit,
+    /// The user pastes the token, for machines without a browser
+    Manual,
+}
+
+impl Default for AuthFlow {
+    fn default() -> Self {
+        AuthFlow::Code
+    }
+}
+
+/// The page the implicit grant redirects to, the token is in the fragment which browsers don't
+/// send, so the page posts it back
+const IMPLICIT_CALLBACK_PAGE: &str = "<!DOCTYPE html>
+<html>
+<body>
+<p id=\"message\">Logging in...</p>
+<script>
+fetch('/token', { method: 'POST', body: window.location.hash.substring(1) })
+    .then(function (response) { return response.text(); })
+    .then(function (message) {
+        document.getElementById('message').textContent = message;
+    });
+</script>
+</body>
+</html>";
+
+const CLOSE_MESSAGE: &str = "You can close this window now.";
+const FAILED_MESSAGE: &str = "Logging in failed, try again from Fubuki.";
+
+/// The address to listen on for the login redirect
+fn listen_address(redirect_uri: &str) -> Result<String> {
+    let url = Url::parse(redirect_uri)?;
+    let host = match url.host_str() {
+        Some("localhost") | None => "127.0.0.1",
+        Some(host) => host,
+    };
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("redirect uri {} has no port", redirect_uri))?;
+    Ok(format!("{}:{}", host, port))
+}
+
+/// A request the browser made to the login listener
+#[derive(Debug)]
+struct CallbackRequest {
+    method: String,
+    /// The path and query
+    target: String,
+    body: String,
+}
+
+impl CallbackRequest {
+    fn read(stream: &TcpStream) -> Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            let mut split = header.splitn(2, ':');
+            let name = split.next().unwrap_or_default().trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = split.next().unwrap_or_default().trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        Ok(CallbackRequest {
+            method,
+            target,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+
+    fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    fn query(&self) -> HashMap<String, String> {
+        let query = self.target.split_once('?').map(|(_, query)| query);
+        parse_pairs(query.unwrap_or_default())
+    }
+}
+
+fn parse_pairs(pairs: &str) -> HashMap<String, String> {
+    form_urlencoded::parse(pairs.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn respond(mut stream: &TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type:{}\r\ncontent-length:{}\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// The url to log in with, `response_type` is "code" or "token"
+fn authorize_url(response_type: &str, state: Option<&str>) -> Result<Url> {
+    let endpoints = settings::endpoints();
+    let mut params = vec![
+        ("client_id", endpoints.client_id),
+        ("redirect_uri", endpoints.redirect_uri),
+        ("response_type", response_type.to_string()),
+    ];
+    if let Some(state) = state {
+        params.push(("state", state.to_string()));
+    }
+    Ok(Url::parse_with_params(&endpoints.authorize, &params)?)
+}
+
+/// Where a token for the manual flow can be got, AniList shows it in the redirect's url
+pub fn manual_login_url() -> Result<String> {
+    Ok(authorize_url("token", None)?.to_string())
+}
+
+/// The token in what the user pasted, either the token or the url the login redirected to
+pub fn token_from_input(input: &str) -> Option<String> {
+    let input = input.trim();
+    let token = match input.find("access_token=") {
+        Some(_) => {
+            let fragment = input
+                .split_once('#')
+                .map_or(input, |(_, fragment)| fragment);
+            parse_pairs(fragment).remove("access_token")?
+        }
+        None => input.to_string(),
+    };
+    if token.is_empty() || token.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// Logs in with the flow, the manual flow has no login to wait for
+pub async fn auth(flow: AuthFlow) -> Result<String> {
+    match flow {
+        AuthFlow::Code => code_grant().await,
+        AuthFlow::Implicit => implicit_grant(),
+        AuthFlow::Manual => Err(anyhow!("the token has to be pasted in the settings")),
+    }
+}
+
+async fn code_grant() -> Result<String> {
     let state = CsrfToken::new_random().secret().to_string();
-    let url_state;
-    let client_id = "2355";
-    let redirect_uri = "http://localhost:8080/callback";
-    let url = Url::parse_with_params(
-        "https://anilist.co/api/v2/oauth/authorize",
-        &[
-            ("client_id", client_id.to_string()),
-            ("redirect_uri", redirect_uri.to_string()),
-            ("response_type", "code".to_string()),
-            ("state", state.to_string()),
-        ],
-    )?;
+    let endpoints = settings::endpoints();
+    let url = authorize_url("code", Some(&state))?;
 
     let mut json = HashMap::new();
     json.insert("grant_type", "authorization_code");
-    json.insert("client_id", client_id);
-    json.insert("redirect_uri", redirect_uri);
+    json.insert("client_id", endpoints.client_id.as_str());
+    json.insert("redirect_uri", endpoints.redirect_uri.as_str());
 
     debug!("attempting to open browser to oauth URL");
     open::that(url.to_string())?;
 
-    let listener = TcpListener::bind("127.0.0.1:8080")?;
-    for stream in listener.incoming() {
-        let mut stream = stream?;
+    let listener = TcpListener::bind(listen_address(&endpoints.redirect_uri)?)?;
+    if let Some(stream) = listener.incoming().next() {
+        let stream = stream?;
         debug!("found ok stream");
-        let mut reader = BufReader::new(&stream);
-        let mut request_line = String::new();
-        reader.read_line(&mut request_line)?;
-
-        if let Some(url_code) = request_line.split_whitespace().nth(1) {
-            let find_key = |key: &str, url: &Url| {
-                if let Some(pair) = url.query_pairs().find(|pair| {
-                    let &(ref k, _) = pair;
-                    k == key
-                }) {
-                    pair.1.into_owned()
-                } else {
-                    String::default()
-                }
-            };
-            let url = format!("http://localhost{}", url_code);
-            let url = Url::parse(&url)?;
-            code = find_key("code", &url);
-            url_state = find_key("state", &url);
-        } else {
-            code = String::default();
-            url_state = String::default();
-        }
-
-        if state != url_state {
+        let request = CallbackRequest::read(&stream)?;
+        let query = request.query();
+        let code = query.get("code").cloned().unwrap_or_default();
+        if query.get("state") != Some(&state) {
             return Err(anyhow!(
                 "state in oauth redirect was not the same as the generated state"
             ));
         }
 
         json.insert("code", &code);
-        let client = reqwest::Client::new();
-        let res = client
-            .post("https://auth.fubuki.dev/oauth/token")
+        let res = super::client()
+            .post(&endpoints.token)
             .header("Accept", "application/json")
             .json(&json)
             .send()
@@ -77,13 +210,7 @@ pub async fn auth() -> Result<String> {
             .text()
             .await?;
 
-        let msg = "You can close this window now.";
-        let response = format!(
-            "HTTP/1.1 200 OK\r\ncontent-length:{}\r\n\r\n{}",
-            msg.len(),
-            msg
-        );
-        stream.write_all(response.as_bytes())?;
+        respond(&stream, "200 OK", "text/plain", CLOSE_MESSAGE)?;
 
         let body: serde_json::Map<String, serde_json::Value> = serde_json::from_str(res.as_str())?;
         if let Some(tok) = body.get("access_token") {
@@ -91,8 +218,122 @@ pub async fn auth() -> Result<String> {
                 return Ok(tok.to_string());
             }
         }
-        break;
     }
 
     Ok(String::default())
 }
+
+fn implicit_grant() -> Result<String> {
+    let state = CsrfToken::new_random().secret().to_string();
+    let endpoints = settings::endpoints();
+    let url = authorize_url("token", Some(&state))?;
+    let callback_path = Url::parse(&endpoints.redirect_uri)?.path().to_string();
+
+    debug!("attempting to open browser to implicit grant URL");
+    open::that(url.to_string())?;
+
+    let listener = TcpListener::bind(listen_address(&endpoints.redirect_uri)?)?;
+    for stream in listener.incoming() {
+        // The browser can connect for other things, like a favicon, which shouldn't stop the login
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!(
+                    "could not accept a connection to the login listener: {}",
+                    err
+                );
+                continue;
+            }
+        };
+        let request = match CallbackRequest::read(&stream) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!("could not read a request to the login listener: {}", err);
+                continue;
+            }
+        };
+        let responded = match (request.method.as_str(), request.path()) {
+            ("GET", path) if path == callback_path => {
+                respond(&stream, "200 OK", "text/html", IMPLICIT_CALLBACK_PAGE)
+            }
+            ("POST", "/token") => {
+                let token = token_from_fragment(&request.body, &state);
+                let (status, message) = match token {
+                    Ok(_) => ("200 OK", CLOSE_MESSAGE),
+                    Err(_) => ("400 Bad Request", FAILED_MESSAGE),
+                };
+                if let Err(err) = respond(&stream, status, "text/plain", message) {
+                    warn!("could not respond to the login redirect: {}", err);
+                }
+                return token;
+            }
+            _ => respond(&stream, "404 Not Found", "text/plain", ""),
+        };
+        if let Err(err) = responded {
+            warn!(
+                "could not respond to a request to the login listener: {}",
+                err
+            );
+        }
+    }
+
+    Err(anyhow!("stopped listening for the login redirect"))
+}
+
+/// The token in the fragment the callback page posted, which has to have the state that was sent
+fn token_from_fragment(body: &str, state: &str) -> Result<String> {
+    let fragment = parse_pairs(body);
+    // Any page open in the browser can post here, only the real redirect has the state
+    if fragment.get("state").map(String::as_str) != Some(state) {
+        return Err(anyhow!(
+            "state in oauth redirect was missing or not the same as the generated state"
+        ));
+    }
+    fragment.get("access_token").cloned().ok_or_else(|| {
+        let error = fragment.get("error").map(String::as_str);
+        anyhow!("no token in the redirect: {}", error.unwrap_or("unknown"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listens_on_the_redirect_port() {
+        assert_eq!(
+            listen_address("http://localhost:8080/callback").unwrap(),
+            "127.0.0.1:8080"
+        );
+        assert_eq!(
+            listen_address("http://192.168.1.2/callback").unwrap(),
+            "192.168.1.2:80"
+        );
+    }
+
+    #[test]
+    fn requires_the_state_of_the_redirect() {
+        let token = token_from_fragment("access_token=abc&token_type=Bearer&state=xyz", "xyz");
+        assert_eq!(token.unwrap(), "abc");
+        assert!(token_from_fragment("access_token=abc", "xyz").is_err());
+        assert!(token_from_fragment("access_token=abc&state=other", "xyz").is_err());
+        assert!(token_from_fragment("error=access_denied&state=xyz", "xyz").is_err());
+    }
+
+    #[test]
+    fn reads_pasted_tokens() {
+        assert_eq!(token_from_input(" abc.def "), Some("abc.def".to_string()));
+        assert_eq!(
+            token_from_input(
+                "http://localhost:8080/callback#access_token=abc.def&token_type=Bearer"
+            ),
+            Some("abc.def".to_string())
+        );
+        assert_eq!(
+            token_from_input("access_token=abc&expires_in=1"),
+            Some("abc".to_string())
+        );
+        assert_eq!(token_from_input(""), None);
+        assert_eq!(token_from_input("not a token"), None);
+    }
+}
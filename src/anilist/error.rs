@@ -0,0 +1,184 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// An error AniList returned in the `errors` of a GraphQL response
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: Option<String>,
+    pub status: Option<i32>,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.message.as_deref().unwrap_or("unknown error");
+        match self.status {
+            Some(status) => write!(f, "{} ({})", message, status),
+            None => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Why a request to AniList failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum AniListError {
+    /// No token is saved, the user has to log in
+    NoToken,
+    /// AniList rejected the token, it was revoked or has expired
+    InvalidToken,
+    /// The request couldn't be sent or its response couldn't be read
+    Transport(String),
+    /// Still rate limited after waiting out the limit a few times
+    RateLimited { retry_after: u64 },
+    /// A failed HTTP status without any GraphQL errors to explain it
+    Http { status: u16, body: String },
+    /// The errors AniList returned for the query
+    GraphQL(Vec<QueryError>),
+    /// The response wasn't what the query should return
+    Deserialize(String),
+    /// The query couldn't be loaded or its variables built
+    Query(String),
+}
+
+/// The longest part of an unexpected response body that is kept
+const MAX_BODY_LEN: usize = 200;
+
+impl AniListError {
+    /// The error for the `errors` of a response, telling apart a rejected token
+    pub fn from_errors(errors: Vec<QueryError>) -> Self {
+        let invalid_token = errors.iter().any(|err| {
+            err.status == Some(401)
+                || err
+                    .message
+                    .as_ref()
+                    .map(|message| message.eq_ignore_ascii_case("invalid token"))
+                    .unwrap_or(false)
+        });
+        if invalid_token {
+            AniListError::InvalidToken
+        } else {
+            AniListError::GraphQL(errors)
+        }
+    }
+
+    pub fn from_status(status: u16, body: &str) -> Self {
+        if status == 401 {
+            return AniListError::InvalidToken;
+        }
+        AniListError::Http {
+            status,
+            body: body.chars().take(MAX_BODY_LEN).collect(),
+        }
+    }
+
+    /// Whether sending the same request again later might work
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AniListError::Transport(_) | AniListError::RateLimited { .. } => true,
+            AniListError::Http { status, .. } => *status >= 500 || *status == 429,
+            AniListError::GraphQL(errors) => errors.iter().any(|err| match err.status {
+                Some(status) => status >= 500 || status == 429,
+                None => false,
+            }),
+            AniListError::NoToken
+            | AniListError::InvalidToken
+            | AniListError::Deserialize(_)
+            | AniListError::Query(_) => false,
+        }
+    }
+
+    /// Whether the user has to log in again before requests can work
+    pub fn needs_login(&self) -> bool {
+        matches!(self, AniListError::NoToken | AniListError::InvalidToken)
+    }
+}
+
+impl fmt::Display for AniListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AniListError::NoToken => write!(f, "not logged in to AniList"),
+            AniListError::InvalidToken => {
+                write!(f, "the AniList login has expired, log in again")
+            }
+            AniListError::Transport(err) => write!(f, "could not reach AniList: {}", err),
+            AniListError::RateLimited { retry_after } => write!(
+                f,
+                "too many requests to AniList, try again in {}s",
+                retry_after
+            ),
+            AniListError::Http { status, body } => {
+                write!(f, "AniList responded with status {}", status)?;
+                if !body.is_empty() {
+                    write!(f, ": {}", body)?;
+                }
+                Ok(())
+            }
+            AniListError::GraphQL(errors) => {
+                let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "AniList returned an error: {}", messages.join(", "))
+            }
+            AniListError::Deserialize(err) => {
+                write!(f, "unexpected response from AniList: {}", err)
+            }
+            AniListError::Query(err) => write!(f, "could not build the query: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AniListError {}
+
+impl From<reqwest::Error> for AniListError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            AniListError::Deserialize(err.to_string())
+        } else {
+            AniListError::Transport(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for AniListError {
+    fn from(err: serde_json::Error) -> Self {
+        AniListError::Deserialize(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors() {
+        let rejected = vec![QueryError {
+            message: Some("Invalid token".to_string()),
+            status: Some(400),
+        }];
+        assert_eq!(
+            AniListError::from_errors(rejected),
+            AniListError::InvalidToken
+        );
+        assert!(AniListError::InvalidToken.needs_login());
+        assert!(!AniListError::InvalidToken.is_retryable());
+
+        let server = AniListError::from_errors(vec![QueryError {
+            message: Some("Internal Server Error".to_string()),
+            status: Some(500),
+        }]);
+        assert!(server.is_retryable());
+        assert_eq!(
+            server.to_string(),
+            "AniList returned an error: Internal Server Error (500)"
+        );
+
+        let validation = AniListError::from_errors(vec![QueryError {
+            message: Some("validation".to_string()),
+            status: Some(400),
+        }]);
+        assert!(!validation.is_retryable());
+
+        assert_eq!(
+            AniListError::from_status(401, ""),
+            AniListError::InvalidToken
+        );
+        assert!(AniListError::from_status(502, "Bad Gateway").is_retryable());
+    }
+}
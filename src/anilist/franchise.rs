@@ -0,0 +1,356 @@
+use super::{query_media_relations, Media, MediaFormat, MediaRelation};
+use crate::settings::file_path;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter},
+    sync::Mutex,
+};
+
+static FRANCHISES: Lazy<Mutex<FranchiseGraph>> = Lazy::new(|| Mutex::new(FranchiseGraph::load()));
+
+/// Most media fetched while following one franchise
+const MAX_FETCHES: usize = 25;
+/// Cached media are fetched again after a week, episode counts change while airing
+const CACHE_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Prequels,
+    Sequels,
+}
+
+/// A prequel or sequel of a media, the format is known before the media is fetched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FranchiseLink {
+    pub id: i32,
+    pub format: Option<MediaFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FranchiseNode {
+    pub id: i32,
+    pub title: Option<String>,
+    pub format: Option<MediaFormat>,
+    pub episodes: Option<i32>,
+    pub prequels: Vec<FranchiseLink>,
+    pub sequels: Vec<FranchiseLink>,
+    /// Unix timestamp of when the media was fetched
+    pub fetched_at: i64,
+}
+
+impl FranchiseNode {
+    pub fn from_media(media: &Media, fetched_at: i64) -> Self {
+        let mut node = FranchiseNode {
+            id: media.id,
+            title: media.preferred_title(),
+            format: media.format.clone(),
+            episodes: media.episodes,
+            prequels: Vec::new(),
+            sequels: Vec::new(),
+            fetched_at,
+        };
+        let edges = media
+            .relations
+            .iter()
+            .filter_map(|relations| relations.edges.as_ref())
+            .flatten()
+            .flatten();
+        for edge in edges {
+            let link = match &edge.node {
+                Some(linked) => FranchiseLink {
+                    id: linked.id,
+                    format: linked.format.clone(),
+                },
+                None => continue,
+            };
+            match edge.relation_type {
+                Some(MediaRelation::Prequel) => node.prequels.push(link),
+                Some(MediaRelation::Sequel) => node.sequels.push(link),
+                _ => {}
+            }
+        }
+        node
+    }
+
+    fn links(&self, direction: Direction) -> &[FranchiseLink] {
+        match direction {
+            Direction::Prequels => &self.prequels,
+            Direction::Sequels => &self.sequels,
+        }
+    }
+}
+
+/// How likely a format is to continue the main story, lower is more likely.
+/// Movies, specials and the like never do.
+fn continuation_rank(format: Option<&MediaFormat>) -> Option<u8> {
+    match format? {
+        MediaFormat::Tv | MediaFormat::TvShort => Some(0),
+        MediaFormat::Ona => Some(1),
+        MediaFormat::Ova => Some(2),
+        _ => None,
+    }
+}
+
+/// The prequels and sequels of the media in a franchise, cached between runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FranchiseGraph {
+    nodes: HashMap<i32, FranchiseNode>,
+}
+
+impl FranchiseGraph {
+    const FILE: &'static str = "franchise_cache.json";
+
+    pub fn load() -> Self {
+        let path = match file_path(Self::FILE) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("could not get franchise cache path: {}", err);
+                return Self::default();
+            }
+        };
+        match File::open(&path) {
+            Ok(file) => match serde_json::from_reader(BufReader::new(file)) {
+                Ok(graph) => graph,
+                Err(err) => {
+                    warn!("could not read {:?}: {}", path, err);
+                    Self::default()
+                }
+            },
+            Err(err) => {
+                debug!("could not open {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = file_path(Self::FILE)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer(BufWriter::new(file), &self)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: i32) -> Option<&FranchiseNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn insert(&mut self, node: FranchiseNode) {
+        self.nodes.insert(node.id, node);
+    }
+
+    /// The continuation to follow out of several. One that links back comes first,
+    /// then TV over ONA over OVA, then the oldest entry on AniList.
+    fn best_link(
+        &self,
+        from: &FranchiseNode,
+        direction: Direction,
+        visited: &HashSet<i32>,
+    ) -> Option<i32> {
+        let back = match direction {
+            Direction::Prequels => Direction::Sequels,
+            Direction::Sequels => Direction::Prequels,
+        };
+        from.links(direction)
+            .iter()
+            .filter(|link| !visited.contains(&link.id))
+            .filter_map(|link| {
+                let linked = self.nodes.get(&link.id);
+                let format = linked.map_or(link.format.as_ref(), |node| node.format.as_ref());
+                let rank = continuation_rank(format)?;
+                let links_back = match linked {
+                    Some(node) => node.links(back).iter().any(|link| link.id == from.id),
+                    None => false,
+                };
+                Some((!links_back, rank, link.id))
+            })
+            .min()
+            .map(|(_, _, id)| id)
+    }
+
+    /// Follows the continuations from the media in one direction, the media included.
+    /// Also returns the media it stopped at if that one isn't known.
+    fn walk(
+        &self,
+        start: i32,
+        direction: Direction,
+        known: &dyn Fn(&FranchiseNode) -> bool,
+    ) -> (Vec<i32>, Option<i32>) {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = start;
+        loop {
+            let node = match self.nodes.get(&current).filter(|node| known(node)) {
+                Some(node) => node,
+                None => return (chain, Some(current)),
+            };
+            visited.insert(current);
+            chain.push(current);
+            match self.best_link(node, direction, &visited) {
+                Some(next) => current = next,
+                None => return (chain, None),
+            }
+        }
+    }
+
+    /// The first media the main story of the media's franchise needs that isn't cached
+    /// or was cached too long ago
+    fn next_missing(&self, start: i32, now: i64) -> Option<i32> {
+        let fresh = |node: &FranchiseNode| now - node.fetched_at < CACHE_SECS;
+        self.walk(start, Direction::Prequels, &fresh)
+            .1
+            .or_else(|| self.walk(start, Direction::Sequels, &fresh).1)
+    }
+
+    /// The TV, ONA and OVA entries telling the main story the media is a part of, in order
+    pub fn main_chain(&self, start: i32) -> Vec<i32> {
+        let (mut chain, _) = self.walk(start, Direction::Prequels, &|_| true);
+        chain.reverse();
+        let (sequels, _) = self.walk(start, Direction::Sequels, &|_| true);
+        chain.extend(sequels.into_iter().skip(1));
+        chain
+    }
+
+    /// Counts the episode on from the start of the chain at the index
+    fn locate(&self, chain: &[i32], from: usize, episode: f64) -> Option<(usize, f64)> {
+        let mut remaining = episode;
+        for (i, id) in chain.iter().enumerate().skip(from) {
+            match self.nodes.get(id)?.episodes {
+                Some(episodes) if remaining > episodes as f64 => remaining -= episodes as f64,
+                Some(_) => return Some((i, remaining)),
+                // Still airing, only the last entry can be
+                None if i == chain.len() - 1 => return Some((i, remaining)),
+                None => return None,
+            }
+        }
+        None
+    }
+
+    /// Maps an episode numbered on past the end of the media to the entry of the franchise
+    /// it's from, and the episode of that entry. Episodes are counted from the first
+    /// season, or from the media itself when that would land before it.
+    pub fn resolve(&self, media_id: i32, episode: f64) -> Option<(i32, f64)> {
+        let media = self.nodes.get(&media_id)?;
+        match media.episodes {
+            Some(episodes) if episode <= episodes as f64 => return Some((media_id, episode)),
+            None => return Some((media_id, episode)),
+            Some(_) => {}
+        }
+
+        let chain = self.main_chain(media_id);
+        let index = chain.iter().position(|id| *id == media_id)?;
+        let located = match self.locate(&chain, 0, episode) {
+            Some((i, episode)) if i >= index => Some((i, episode)),
+            _ => self.locate(&chain, index, episode),
+        };
+        located.map(|(i, episode)| (chain[i], episode))
+    }
+}
+
+/// Fetches the main story of the media's franchise into the cache, returning the graph
+pub async fn fetch_franchise(token: Option<String>, media_id: i32) -> Result<FranchiseGraph> {
+    let now = Utc::now().timestamp();
+    let mut fetched = 0;
+    loop {
+        let missing = FRANCHISES.lock().unwrap().next_missing(media_id, now);
+        let id = match missing {
+            Some(id) => id,
+            None => break,
+        };
+        if fetched == MAX_FETCHES {
+            warn!(
+                "stopped following the franchise of media {} after {} requests",
+                media_id, fetched
+            );
+            break;
+        }
+        let media = query_media_relations(token.clone(), id)
+            .await?
+            .media
+            .ok_or_else(|| anyhow!("no relations returned for media {}", id))?;
+        fetched += 1;
+        FRANCHISES
+            .lock()
+            .unwrap()
+            .insert(FranchiseNode::from_media(&media, now));
+    }
+
+    let graph = FRANCHISES.lock().unwrap().clone();
+    if fetched > 0 {
+        debug!("fetched {} media of the franchise of {}", fetched, media_id);
+        if let Err(err) = graph.save() {
+            warn!("could not save franchise cache: {}", err);
+        }
+    }
+    Ok(graph)
+}
+
+/// The entry of the media's franchise and its episode the absolute episode is
+pub async fn resolve_episode(
+    token: Option<String>,
+    media_id: i32,
+    episode: f64,
+) -> Result<Option<(i32, f64)>> {
+    let graph = fetch_franchise(token, media_id).await?;
+    Ok(graph.resolve(media_id, episode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(fixture: &str) -> FranchiseGraph {
+        let media: Vec<Media> = serde_json::from_str(fixture).unwrap();
+        let mut graph = FranchiseGraph::default();
+        for media in media.iter() {
+            graph.insert(FranchiseNode::from_media(media, 0));
+        }
+        graph
+    }
+
+    #[test]
+    fn orders_the_main_story() {
+        let graph = graph(include_str!(
+            "../../tests/fixtures/franchise/split_cour.json"
+        ));
+        assert_eq!(graph.main_chain(101), vec![100, 101, 102, 104]);
+        assert_eq!(graph.main_chain(104), vec![100, 101, 102, 104]);
+        assert_eq!(graph.next_missing(100, 0), None);
+        assert_eq!(graph.next_missing(100, CACHE_SECS), Some(100));
+    }
+
+    #[test]
+    fn resolves_absolute_episodes() {
+        let graph = graph(include_str!(
+            "../../tests/fixtures/franchise/split_cour.json"
+        ));
+        assert_eq!(graph.resolve(100, 5.0), Some((100, 5.0)));
+        assert_eq!(graph.resolve(100, 13.0), Some((101, 1.0)));
+        assert_eq!(graph.resolve(100, 30.0), Some((102, 6.0)));
+        assert_eq!(graph.resolve(101, 30.0), Some((102, 6.0)));
+        // Numbered on from the third season, since counting from the first lands before it
+        assert_eq!(graph.resolve(102, 15.0), Some((104, 2.0)));
+        assert_eq!(graph.resolve(100, 48.5), Some((104, 11.5)));
+        assert_eq!(graph.resolve(100, 60.0), None);
+    }
+
+    #[test]
+    fn follows_airing_onas() {
+        let graph = graph(include_str!(
+            "../../tests/fixtures/franchise/airing_ona.json"
+        ));
+        assert_eq!(graph.main_chain(200), vec![200, 201]);
+        assert_eq!(graph.resolve(200, 30.0), Some((201, 6.0)));
+        assert_eq!(graph.resolve(200, 100.0), Some((201, 76.0)));
+        assert_eq!(graph.next_missing(200, 0), Some(202));
+    }
+}
@@ -0,0 +1,223 @@
+use chrono::Utc;
+use log::debug;
+use once_cell::sync::Lazy;
+use reqwest::{header::HeaderMap, StatusCode};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+/// Every request to AniList waits for its turn here
+pub static LIMITER: Lazy<RateLimiter> = Lazy::new(RateLimiter::default);
+
+/// Requests per minute until AniList's headers say otherwise
+const DEFAULT_LIMIT: u32 = 90;
+/// How often waiting requests check the bucket again
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    /// Something the user is waiting on, like a search or a list update
+    Interactive,
+    /// Work that can wait, like list refreshes, which leaves some requests for interactive ones
+    Background,
+}
+
+/// A token bucket refilling at AniList's per minute limit
+#[derive(Debug)]
+struct Bucket {
+    limit: u32,
+    tokens: f64,
+    refilled: Instant,
+    /// Set after a 429 until the limit resets
+    blocked_until: Option<Instant>,
+    /// Interactive requests waiting for a token, background ones wait until there are none
+    interactive_waiting: usize,
+}
+
+impl Bucket {
+    fn new(limit: u32, now: Instant) -> Self {
+        Bucket {
+            limit,
+            tokens: limit as f64,
+            refilled: now,
+            blocked_until: None,
+            interactive_waiting: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit as f64 / 60.0).min(self.limit as f64);
+        self.refilled = now;
+    }
+
+    /// The tokens background requests leave for interactive ones
+    fn reserve(&self) -> f64 {
+        (self.limit / 10).max(1) as f64
+    }
+
+    /// Takes a token, or says how long to wait before trying again
+    fn take(&mut self, priority: Priority, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.blocked_until = None;
+        }
+        self.refill(now);
+        let needed = match priority {
+            Priority::Interactive => 1.0,
+            Priority::Background if self.interactive_waiting > 0 => return Err(POLL_INTERVAL),
+            Priority::Background => 1.0 + self.reserve(),
+        };
+        if self.tokens >= needed {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let per_token = 60.0 / self.limit.max(1) as f64;
+            Err(Duration::from_secs_f64((needed - self.tokens) * per_token).max(POLL_INTERVAL))
+        }
+    }
+
+    /// Follows the limit AniList reports, blocking until the reset after a 429
+    fn observe(&mut self, status: StatusCode, headers: &HeaderMap, now: Instant) {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|val| val.to_str().ok())
+                .and_then(|val| val.trim().parse::<i64>().ok())
+        };
+        if let Some(limit) = header("X-RateLimit-Limit") {
+            if limit > 0 {
+                self.limit = limit as u32;
+            }
+        }
+        self.refill(now);
+        if let Some(remaining) = header("X-RateLimit-Remaining") {
+            self.tokens = self.tokens.min(remaining.max(0) as f64);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let wait = match (header("Retry-After"), header("X-RateLimit-Reset")) {
+                (Some(secs), _) => secs,
+                (None, Some(reset)) => reset - Utc::now().timestamp(),
+                (None, None) => 60,
+            };
+            let wait = Duration::from_secs(wait.max(1) as u64);
+            debug!("rate limited by AniList for {}s", wait.as_secs());
+            self.tokens = 0.0;
+            self.blocked_until = Some(now + wait);
+        }
+    }
+}
+
+/// Counts an interactive request as waiting until it's dropped, so cancelled ones stop counting
+struct InteractiveWaiting<'a>(&'a Mutex<Bucket>);
+
+impl<'a> InteractiveWaiting<'a> {
+    fn new(bucket: &'a Mutex<Bucket>) -> Self {
+        bucket.lock().unwrap().interactive_waiting += 1;
+        InteractiveWaiting(bucket)
+    }
+}
+
+impl Drop for InteractiveWaiting<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().interactive_waiting -= 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter {
+            bucket: Mutex::new(Bucket::new(DEFAULT_LIMIT, Instant::now())),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Waits until a request with the priority can be sent
+    pub async fn acquire(&self, priority: Priority) {
+        let _waiting = match priority {
+            Priority::Interactive => Some(InteractiveWaiting::new(&self.bucket)),
+            Priority::Background => None,
+        };
+        loop {
+            let taken = self.bucket.lock().unwrap().take(priority, Instant::now());
+            match taken {
+                Ok(()) => break,
+                Err(wait) => time::delay_for(wait).await,
+            }
+        }
+    }
+
+    /// Waits while interactive requests are waiting, for background work that isn't an API request
+    pub async fn yield_to_interactive(&self) {
+        while self.bucket.lock().unwrap().interactive_waiting > 0 {
+            time::delay_for(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Reads the rate limit headers of a response
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap) {
+        self.bucket
+            .lock()
+            .unwrap()
+            .observe(status, headers, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_requests_by_priority() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(20, now);
+        bucket.tokens = 2.0;
+        // Background requests leave the reserve alone
+        assert!(bucket.take(Priority::Background, now).is_err());
+        assert!(bucket.take(Priority::Interactive, now).is_ok());
+        assert!(bucket.take(Priority::Interactive, now).is_ok());
+        assert_eq!(
+            bucket.take(Priority::Interactive, now),
+            Err(Duration::from_secs(3))
+        );
+        assert!(bucket
+            .take(Priority::Interactive, now + Duration::from_secs(3))
+            .is_ok());
+
+        bucket.tokens = 20.0;
+        bucket.interactive_waiting = 1;
+        assert_eq!(bucket.take(Priority::Background, now), Err(POLL_INTERVAL));
+    }
+
+    #[test]
+    fn follows_rate_limit_headers() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(DEFAULT_LIMIT, now);
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Limit", "30".parse().unwrap());
+        headers.insert("X-RateLimit-Remaining", "4".parse().unwrap());
+        bucket.observe(StatusCode::OK, &headers, now);
+        assert_eq!(bucket.limit, 30);
+        assert_eq!(bucket.tokens, 4.0);
+
+        headers.insert("Retry-After", "30".parse().unwrap());
+        bucket.observe(StatusCode::TOO_MANY_REQUESTS, &headers, now);
+        assert_eq!(
+            bucket.take(Priority::Interactive, now),
+            Err(Duration::from_secs(30))
+        );
+        assert!(bucket
+            .take(Priority::Interactive, now + Duration::from_secs(30))
+            .is_ok());
+    }
+}
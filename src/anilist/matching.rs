@@ -0,0 +1,510 @@
+use super::{Media, MediaFormat};
+use crate::{
+    recognition::{NormalizedTitle, Normalizer},
+    settings,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BTreeSet,
+    fmt::Debug,
+};
+
+/// Scores how well a title from AniList matches a searched title, from 0 to 1
+pub trait MatchScorer: Debug + Send + Sync {
+    fn score(
+        &self,
+        search: &NormalizedTitle,
+        title: &NormalizedTitle,
+        format: Option<&MediaFormat>,
+    ) -> f64;
+}
+
+#[derive(Debug)]
+pub struct Levenshtein;
+
+impl MatchScorer for Levenshtein {
+    fn score(
+        &self,
+        search: &NormalizedTitle,
+        title: &NormalizedTitle,
+        _: Option<&MediaFormat>,
+    ) -> f64 {
+        strsim::normalized_levenshtein(&search.key, &title.key)
+    }
+}
+
+#[derive(Debug)]
+pub struct JaroWinkler;
+
+impl MatchScorer for JaroWinkler {
+    fn score(
+        &self,
+        search: &NormalizedTitle,
+        title: &NormalizedTitle,
+        _: Option<&MediaFormat>,
+    ) -> f64 {
+        strsim::jaro_winkler(&search.key, &title.key)
+    }
+}
+
+/// Compares the words both titles share against the words only one has, ignoring word order
+#[derive(Debug)]
+pub struct TokenSet;
+
+impl TokenSet {
+    pub fn ratio(a: &str, b: &str) -> f64 {
+        let a: BTreeSet<&str> = a.split_whitespace().collect();
+        let b: BTreeSet<&str> = b.split_whitespace().collect();
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+        let join = |words: Vec<&str>| words.join(" ");
+        let common = join(a.intersection(&b).cloned().collect());
+        let only_a = join(a.difference(&b).cloned().collect());
+        let only_b = join(b.difference(&a).cloned().collect());
+        let with = |rest: &str| format!("{} {}", common, rest).trim().to_string();
+        let (with_a, with_b) = (with(&only_a), with(&only_b));
+
+        let mut ratio = strsim::normalized_levenshtein(&with_a, &with_b);
+        if !common.is_empty() {
+            ratio = ratio
+                .max(strsim::normalized_levenshtein(&common, &with_a))
+                .max(strsim::normalized_levenshtein(&common, &with_b));
+        }
+        ratio
+    }
+}
+
+impl MatchScorer for TokenSet {
+    fn score(
+        &self,
+        search: &NormalizedTitle,
+        title: &NormalizedTitle,
+        _: Option<&MediaFormat>,
+    ) -> f64 {
+        Self::ratio(&search.key, &title.key)
+    }
+}
+
+/// Averages other scorers, then adjusts for the season and format hints of the titles
+#[derive(Debug)]
+pub struct Weighted {
+    pub scorers: Vec<(Box<dyn MatchScorer>, f64)>,
+    /// Taken off titles that name a different season than the search
+    pub season_penalty: f64,
+    /// Added when the search names the media's format, taken off when it names another one
+    pub format_weight: f64,
+}
+
+impl Default for Weighted {
+    fn default() -> Self {
+        Weighted {
+            scorers: vec![
+                (Box::new(Levenshtein), 0.4),
+                (Box::new(JaroWinkler), 0.3),
+                (Box::new(TokenSet), 0.3),
+            ],
+            season_penalty: 0.1,
+            format_weight: 0.05,
+        }
+    }
+}
+
+impl MatchScorer for Weighted {
+    fn score(
+        &self,
+        search: &NormalizedTitle,
+        title: &NormalizedTitle,
+        format: Option<&MediaFormat>,
+    ) -> f64 {
+        let total_weight: f64 = self.scorers.iter().map(|(_, weight)| weight).sum();
+        if total_weight <= 0.0 {
+            return 0.0;
+        }
+        let mut score = self
+            .scorers
+            .iter()
+            .map(|(scorer, weight)| scorer.score(search, title, format) * weight)
+            .sum::<f64>()
+            / total_weight;
+
+        if !search.hint.same_season(&title.hint) {
+            score -= self.season_penalty;
+        }
+        if let (Some(wanted), Some(format)) = (&search.hint.format, format) {
+            if wanted == format {
+                score += self.format_weight;
+            } else {
+                score -= self.format_weight;
+            }
+        }
+        score.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScorerKind {
+    Levenshtein,
+    JaroWinkler,
+    TokenSet,
+    Weighted,
+}
+
+impl ScorerKind {
+    pub fn scorer(self) -> Box<dyn MatchScorer> {
+        match self {
+            ScorerKind::Levenshtein => Box::new(Levenshtein),
+            ScorerKind::JaroWinkler => Box::new(JaroWinkler),
+            ScorerKind::TokenSet => Box::new(TokenSet),
+            ScorerKind::Weighted => Box::new(Weighted::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchSettings {
+    pub scorer: ScorerKind,
+    /// Candidates scoring lower than this are dropped
+    pub min_confidence: f64,
+    /// The best candidate is used without asking when it scores at least this much
+    pub auto_accept: f64,
+    /// How far ahead of the runner up the best candidate has to be to be used without asking
+    pub ambiguity_margin: f64,
+    /// How many candidates are offered when asking
+    pub max_choices: usize,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            scorer: ScorerKind::Weighted,
+            min_confidence: 0.75,
+            auto_accept: 0.9,
+            ambiguity_margin: 0.05,
+            max_choices: 5,
+        }
+    }
+}
+
+impl MatchSettings {
+    /// The settings with a negative margin put back to the default, which would be meaningless
+    pub fn validated(self) -> Self {
+        if self.ambiguity_margin >= 0.0 {
+            return self;
+        }
+        warn!(
+            "ignoring negative ambiguity_margin {} in the match settings",
+            self.ambiguity_margin
+        );
+        MatchSettings {
+            ambiguity_margin: MatchSettings::default().ambiguity_margin,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoAddMode {
+    /// Media that isn't in the list is never matched
+    Off,
+    /// Ask before adding the best match to the list
+    Ask,
+    /// Add the best match without asking when it's confident enough
+    Automatic,
+}
+
+impl Default for AutoAddMode {
+    fn default() -> Self {
+        AutoAddMode::Off
+    }
+}
+
+/// What happens when detected media only matches search results that aren't in the user's list
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoAddSettings {
+    pub mode: AutoAddMode,
+    /// Automatic mode asks about matches scoring lower than this
+    pub min_confidence: f64,
+}
+
+impl Default for AutoAddSettings {
+    fn default() -> Self {
+        AutoAddSettings {
+            mode: AutoAddMode::Off,
+            min_confidence: 0.95,
+        }
+    }
+}
+
+impl AutoAddSettings {
+    /// How a match with the confidence is added
+    pub fn mode_for(&self, confidence: f64) -> AutoAddMode {
+        match self.mode {
+            AutoAddMode::Automatic if confidence < self.min_confidence => AutoAddMode::Ask,
+            mode => mode,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCandidate {
+    pub media_id: i32,
+    pub title: String,
+    /// The title of the media that scored best
+    pub matched_title: String,
+    pub format: Option<MediaFormat>,
+    pub episodes: Option<i32>,
+    pub confidence: f64,
+    pub licensed: bool,
+}
+
+impl MatchCandidate {
+    /// How well the format and length fit the detected episode, higher fits better. Detections
+    /// with an episode are more likely a series, ones without are more likely a movie.
+    fn fit(&self, episode: Option<f64>) -> u8 {
+        if let (Some(episode), Some(episodes)) = (episode, self.episodes) {
+            if episode > episodes as f64 {
+                return 0;
+            }
+        }
+        let series = matches!(
+            self.format,
+            Some(MediaFormat::Tv) | Some(MediaFormat::TvShort) | Some(MediaFormat::Ona)
+        );
+        match episode {
+            Some(_) if series => 2,
+            None if self.episodes == Some(1) => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchDecision {
+    Accept(MatchCandidate),
+    /// Several candidates are close, the user should pick one
+    Ambiguous(Vec<MatchCandidate>),
+    NoMatch,
+}
+
+#[derive(Debug)]
+pub struct Matcher {
+    scorer: Box<dyn MatchScorer>,
+    normalizer: Normalizer,
+    settings: MatchSettings,
+}
+
+impl Matcher {
+    pub fn new(settings: MatchSettings, normalizer: Normalizer) -> Self {
+        Matcher {
+            scorer: settings.scorer.scorer(),
+            normalizer,
+            settings,
+        }
+    }
+
+    pub fn from_settings() -> Self {
+        let settings = settings::get_settings().read().unwrap();
+        Self::new(settings.matching, Normalizer::new(settings.normalize))
+    }
+
+    pub fn settings(&self) -> &MatchSettings {
+        &self.settings
+    }
+
+    /// The best score out of all of the media's titles and the title that got it
+    pub fn score_media<'a>(
+        &self,
+        search: &NormalizedTitle,
+        media: &'a Media,
+    ) -> (f64, Option<&'a String>) {
+        let mut best = (0.0, None);
+        for title in media.all_titles() {
+            let normalized = self.normalizer.normalize(title);
+            let score = self
+                .scorer
+                .score(search, &normalized, media.format.as_ref());
+            if score > best.0 {
+                best = (score, Some(title));
+            }
+        }
+        best
+    }
+
+    pub fn score_title(&self, search: &str, media: &Media) -> f64 {
+        self.score_media(&self.normalizer.normalize(search), media)
+            .0
+    }
+
+    /// Every media scoring at least the minimum confidence, best first.
+    /// Licensed media wins ties since a licensed version exists.
+    pub fn rank(&self, media: &[&Media], search: &str) -> Vec<MatchCandidate> {
+        self.rank_above(media, search, self.settings.min_confidence)
+    }
+
+    /// Same as `rank`, but with the minimum confidence given
+    pub fn rank_above(
+        &self,
+        media: &[&Media],
+        search: &str,
+        min_confidence: f64,
+    ) -> Vec<MatchCandidate> {
+        let search = self.normalizer.normalize(search);
+        let mut candidates: Vec<MatchCandidate> = media
+            .iter()
+            .filter_map(|media| {
+                let (confidence, matched_title) = self.score_media(&search, media);
+                if confidence < min_confidence {
+                    return None;
+                }
+                Some(MatchCandidate {
+                    media_id: media.id,
+                    title: media
+                        .preferred_title()
+                        .or_else(|| matched_title.cloned())
+                        .unwrap_or_default(),
+                    matched_title: matched_title.cloned().unwrap_or_default(),
+                    format: media.format.clone(),
+                    episodes: media.episodes,
+                    confidence,
+                    licensed: media.is_licensed.unwrap_or(false),
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(Ordering::Equal)
+                .then(b.licensed.cmp(&a.licensed))
+        });
+        candidates
+    }
+
+    /// Picks the best of the ranked candidates for the detected episode, or asks when several
+    /// are close. Candidates within the margin of the best are told apart by `MatchCandidate::fit`
+    /// first, so a series and its movie of the same name don't always need asking about.
+    pub fn decide(&self, mut ranked: Vec<MatchCandidate>, episode: Option<f64>) -> MatchDecision {
+        let top = match ranked.first() {
+            Some(best) => best.confidence,
+            None => return MatchDecision::NoMatch,
+        };
+        // The best is always close to itself, even with no margin
+        let close = ranked
+            .iter()
+            .take_while(|candidate| top - candidate.confidence < self.settings.ambiguity_margin)
+            .count()
+            .max(1);
+        ranked[..close].sort_by_key(|candidate| Reverse(candidate.fit(episode)));
+        let best = &ranked[0];
+        let clear_winner = ranked[1..close]
+            .iter()
+            .all(|other| best.fit(episode) > other.fit(episode));
+        if best.confidence >= self.settings.auto_accept && clear_winner {
+            MatchDecision::Accept(ranked.swap_remove(0))
+        } else {
+            ranked.truncate(self.settings.max_choices);
+            MatchDecision::Ambiguous(ranked)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media(id: i32, title: &str, format: &str) -> Media {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": { "romaji": title, "userPreferred": title },
+            "format": format,
+            "episodes": if format == "TV" { 12 } else { 1 },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn token_set_ignores_word_order() {
+        assert_eq!(TokenSet::ratio("name show", "show name"), 1.0);
+        assert_eq!(TokenSet::ratio("", "show name"), 0.0);
+        assert!(TokenSet::ratio("show name", "other title") < 0.5);
+    }
+
+    #[test]
+    fn ranks_the_right_season_first() {
+        let matcher = Matcher::new(MatchSettings::default(), Normalizer::default());
+        let first = media(1, "Show Name", "TV");
+        let second = media(2, "Show Name 2nd Season", "TV");
+        let other = media(3, "Unrelated Title", "TV");
+        let all = vec![&first, &second, &other];
+
+        let ranked = matcher.rank(&all, "Show Name Season 2");
+        assert_eq!(ranked[0].media_id, 2);
+        assert_eq!(ranked[0].confidence, 1.0);
+        assert!(ranked.iter().all(|candidate| candidate.media_id != 3));
+        assert!(matches!(
+            matcher.decide(ranked, Some(3.0)),
+            MatchDecision::Accept(MatchCandidate { media_id: 2, .. })
+        ));
+
+        let ranked = matcher.rank(&all, "SHOW NAME (TV)");
+        assert_eq!(ranked[0].media_id, 1);
+    }
+
+    #[test]
+    fn close_candidates_are_ambiguous() {
+        let matcher = Matcher::new(MatchSettings::default(), Normalizer::default());
+        let tv = media(1, "Show Name", "TV");
+        let other_tv = media(2, "Show Name", "TV");
+        let ranked = matcher.rank(&[&tv, &other_tv], "Show Name");
+        match matcher.decide(ranked, Some(3.0)) {
+            MatchDecision::Ambiguous(candidates) => assert_eq!(candidates.len(), 2),
+            decision => panic!("expected an ambiguous match, got {:?}", decision),
+        }
+        assert_eq!(matcher.decide(Vec::new(), None), MatchDecision::NoMatch);
+
+        let no_margin = Matcher::new(
+            MatchSettings {
+                ambiguity_margin: 0.0,
+                ..MatchSettings::default()
+            },
+            Normalizer::default(),
+        );
+        let negative = MatchSettings {
+            ambiguity_margin: -1.0,
+            ..MatchSettings::default()
+        };
+        assert_eq!(negative.validated(), MatchSettings::default());
+        let ranked = no_margin.rank(&[&tv, &other_tv], "Show Name");
+        assert!(matches!(
+            no_margin.decide(ranked, Some(3.0)),
+            MatchDecision::Accept(MatchCandidate { media_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn series_and_movie_are_told_apart() {
+        let matcher = Matcher::new(MatchSettings::default(), Normalizer::default());
+        let tv = media(1, "Show Name", "TV");
+        let movie = media(2, "Show Name", "MOVIE");
+        let decide = |episode| matcher.decide(matcher.rank(&[&movie, &tv], "Show Name"), episode);
+        assert!(matches!(
+            decide(Some(5.0)),
+            MatchDecision::Accept(MatchCandidate { media_id: 1, .. })
+        ));
+        assert!(matches!(
+            decide(None),
+            MatchDecision::Accept(MatchCandidate { media_id: 2, .. })
+        ));
+        // Episode 1 could be either
+        assert!(matches!(
+            decide(Some(1.0)),
+            MatchDecision::Accept(MatchCandidate { media_id: 1, .. })
+        ));
+    }
+}
@@ -1,7 +1,23 @@
 pub mod auth;
+pub mod error;
+pub mod franchise;
+pub mod limiter;
+pub mod matching;
 pub mod models;
+pub mod queue;
+pub mod relations;
 pub mod request;
+pub mod status;
+pub mod transport;
 
 pub use auth::*;
+pub use error::*;
+pub use franchise::*;
+pub use limiter::*;
+pub use matching::*;
 pub use models::*;
+pub use queue::*;
+pub use relations::*;
 pub use request::*;
+pub use status::*;
+pub use transport::*;
@@ -1,3 +1,4 @@
+use super::{MatchCandidate, Matcher, ProgressUpdate, StatusChange, StatusRules, TransitionMode};
 use chrono::{offset::Local, NaiveDate};
 use log::{debug, warn};
 use serde::{self, Deserialize, Serialize};
@@ -176,6 +177,29 @@ impl MediaListCollection {
         }
     }
 
+    /// The id of a special related to the media that's in the list, recaps first
+    pub fn find_special_entry(&self, id: i32) -> Option<i32> {
+        let media = self.find_entry_by_id(id)?.media.as_ref()?;
+        let mut edges: Vec<&MediaEdge> = media
+            .relations
+            .as_ref()?
+            .edges
+            .as_ref()?
+            .iter()
+            .filter_map(|edge| edge.as_ref())
+            .filter(|edge| match &edge.node {
+                Some(node) => node.format == Some(MediaFormat::Special),
+                None => false,
+            })
+            .collect();
+        edges.sort_by_key(|edge| !matches!(edge.relation_type, Some(MediaRelation::Summary)));
+        edges
+            .iter()
+            .filter_map(|edge| edge.node.as_ref())
+            .map(|node| node.id)
+            .find(|id| self.find_entry_by_id(*id).is_some())
+    }
+
     pub fn find_entry_by_id(&self, id: i32) -> Option<&MediaList> {
         let lists = self.lists.as_ref()?;
         let lists: Vec<&MediaListGroup> = lists
@@ -260,75 +284,85 @@ impl MediaListCollection {
             .map(|media| Some(media))
             .collect();
 
-        Self::best_id_for_search(&media, search, oneshot)
+        let ranked = Self::rank_search_results(&media, search, oneshot);
+        ranked.first().map(|candidate| candidate.media_id)
     }
 
-    pub fn best_id_for_search(
+    /// Media in the user's lists matching the search, best match first
+    pub fn rank_search_results(
         media: &[Option<&Media>],
         search: &str,
         oneshot: bool,
-    ) -> Option<i32> {
-        let mut media_in_list: Vec<&Media> = media
+    ) -> Vec<MatchCandidate> {
+        let media_in_list = Self::search_results_in_list(media, oneshot);
+        Matcher::from_settings().rank(&media_in_list, search)
+    }
+
+    /// The search results that are in the user's lists, only oneshots if looking for one
+    pub fn search_results_in_list<'a>(
+        media: &[Option<&'a Media>],
+        oneshot: bool,
+    ) -> Vec<&'a Media> {
+        Self::search_results_of_kind(media, oneshot)
             .into_iter()
-            .filter_map(|media| *media)
-            .filter_map(|media| match media.media_list_entry {
-                Some(_) => Some(media),
-                None => None,
-            })
+            .filter(|media| media.media_list_entry.is_some())
+            .collect()
+    }
+
+    /// The search results that aren't in the user's lists, best match first
+    pub fn rank_search_results_not_in_list(
+        media: &[Option<&Media>],
+        search: &str,
+        oneshot: bool,
+    ) -> Vec<MatchCandidate> {
+        let media_not_in_list: Vec<&Media> = Self::search_results_of_kind(media, oneshot)
+            .into_iter()
+            .filter(|media| media.media_list_entry.is_none())
             .collect();
+        Matcher::from_settings().rank(&media_not_in_list, search)
+    }
 
-        // Look for oneshots only
-        if oneshot {
-            media_in_list = media_in_list
-                .into_iter()
-                .filter_map(|media| match media.format {
-                    Some(MediaFormat::Oneshot) => Some(media),
-                    _ => None,
-                })
-                .collect();
-        } else {
-            // filter out oneshots
-            media_in_list = media_in_list
-                .into_iter()
-                .filter_map(|media| match media.format {
-                    Some(MediaFormat::Oneshot) => None,
-                    Some(_) => Some(media),
-                    None => None,
-                })
-                .collect();
-        }
+    /// Only oneshots if looking for one, otherwise everything else
+    fn search_results_of_kind<'a>(media: &[Option<&'a Media>], oneshot: bool) -> Vec<&'a Media> {
+        media
+            .iter()
+            .filter_map(|media| *media)
+            .filter(|media| match media.format {
+                Some(MediaFormat::Oneshot) => oneshot,
+                Some(_) => !oneshot,
+                None => false,
+            })
+            .collect()
+    }
 
-        let mut best_is_licensed = false;
-        let mut best_id = None;
-        let mut best_sim = 0.0;
-        for media in media_in_list {
-            for title in media.all_titles() {
-                let license = media.is_licensed.unwrap_or(false);
-                let sim = strsim::normalized_levenshtein(title, search);
-                // debug!("    similarity of {} between {} and {}", sim, search, title);
-                if sim >= 0.85 {
-                    if sim > best_sim {
-                        best_id = Some(media.id);
-                        best_sim = sim;
-                        best_is_licensed = license;
-                    } else if sim == best_sim && !best_is_licensed {
-                        // Prioritize licensed media over non-licensed media since a licensed version exists
-                        best_id = Some(media.id);
-                        best_sim = sim;
-                        best_is_licensed = license;
-                    }
-                }
-            }
+    /// Adds the entry to the list of its status, making the list if there isn't one
+    pub fn insert_entry(&mut self, entry: MediaList, media_type: MediaType) {
+        let lists = self.lists.get_or_insert_with(Vec::new);
+        let group = lists
+            .iter_mut()
+            .filter_map(|group| group.as_mut())
+            .find(|group| !group.is_custom_list.unwrap_or(false) && group.status == entry.status);
+        match group {
+            Some(group) => group.entries.get_or_insert_with(Vec::new).push(Some(entry)),
+            None => lists.push(Some(MediaListGroup {
+                name: entry
+                    .status
+                    .as_ref()
+                    .map(|status| status.list_name(media_type).to_string()),
+                is_custom_list: Some(false),
+                is_split_completed_list: Some(false),
+                status: entry.status.clone(),
+                entries: Some(vec![Some(entry)]),
+            })),
         }
-
-        best_id
     }
 
     pub fn search_for_title(&mut self, search: &str) -> Option<&mut MediaList> {
         let lists = self.lists.as_mut()?;
+        let matcher = Matcher::from_settings();
         let mut found_entries = Vec::new();
         for list_group in lists.iter_mut() {
-            let (entry, sim) = Self::search_entries(list_group.as_mut(), search);
+            let (entry, sim) = Self::search_entries(list_group.as_mut(), search, &matcher);
             if sim == 1 as f64 {
                 return entry;
             } else if entry.is_some() {
@@ -350,6 +384,7 @@ impl MediaListCollection {
     fn search_entries<'a>(
         list_group: Option<&'a mut MediaListGroup>,
         search: &str,
+        matcher: &Matcher,
     ) -> (Option<&'a mut MediaList>, f64) {
         let entries = match list_group.and_then(|list_group| list_group.entries.as_mut()) {
             Some(entries) => entries,
@@ -358,7 +393,7 @@ impl MediaListCollection {
 
         let mut found_entries = Vec::new();
         for entry in entries {
-            let (entry, sim) = Self::entry_matching_search(entry, search);
+            let (entry, sim) = Self::entry_matching_search(entry, search, matcher);
             if sim == 1 as f64 {
                 return (entry, sim);
             } else if entry.is_some() {
@@ -380,24 +415,21 @@ impl MediaListCollection {
     fn entry_matching_search<'a>(
         entry: &'a mut Option<MediaList>,
         search: &str,
+        matcher: &Matcher,
     ) -> (Option<&'a mut MediaList>, f64) {
         let entry = entry.as_mut();
         if let Some(entry) = entry {
             let media = entry.media.as_ref();
             if let Some(media) = media {
-                for title in media.all_titles() {
-                    let sim = if title.is_ascii() && search.is_ascii() {
-                        strsim::normalized_levenshtein(
-                            &title.to_lowercase(),
-                            &search.to_lowercase(),
-                        )
-                    } else {
-                        strsim::normalized_levenshtein(title, search)
-                    };
-                    debug!("    similarity of {} between {} and {}", sim, search, title);
-                    if sim >= 0.85 {
-                        return (Some(entry), sim);
-                    }
+                let sim = matcher.score_title(search, media);
+                debug!(
+                    "    similarity of {} between {} and {:?}",
+                    sim,
+                    search,
+                    media.preferred_title()
+                );
+                if sim >= matcher.settings().min_confidence {
+                    return (Some(entry), sim);
                 }
             }
         }
@@ -501,7 +533,7 @@ impl MediaListGroup {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MediaListStatus {
     Current,
@@ -512,6 +544,22 @@ pub enum MediaListStatus {
     Repeating,
 }
 
+impl MediaListStatus {
+    /// The name AniList gives the list of entries with the status
+    pub fn list_name(&self, media_type: MediaType) -> &'static str {
+        match (self, media_type) {
+            (MediaListStatus::Current, MediaType::Anime) => "Watching",
+            (MediaListStatus::Current, MediaType::Manga) => "Reading",
+            (MediaListStatus::Planning, _) => "Planning",
+            (MediaListStatus::Completed, _) => "Completed",
+            (MediaListStatus::Dropped, _) => "Dropped",
+            (MediaListStatus::Paused, _) => "Paused",
+            (MediaListStatus::Repeating, MediaType::Anime) => "Rewatching",
+            (MediaListStatus::Repeating, MediaType::Manga) => "Rereading",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct MediaList {
@@ -521,28 +569,131 @@ pub struct MediaList {
     pub progress: Option<i32>,
     pub progress_volumes: Option<i32>,
     pub score: Option<f64>,
-    // repeat, priority, private, notes, hiddenFromStatusLists, customLists
+    /// How many times the media has been rewatched or reread
+    pub repeat: Option<i32>,
+    // priority, private, notes, hiddenFromStatusLists, customLists
     // startedAt, completedAt,
     pub started_at: Option<FuzzyDate>,
     pub completed_at: Option<FuzzyDate>,
     pub media: Option<Media>,
 }
 
+/// What happens when a completed media is watched or read again from the start
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    /// Ask whether it's a rewatch before changing the list
+    Ask,
+    /// Always switch the entry to Repeating
+    Always,
+    /// Leave completed entries alone
+    Never,
+}
+
+impl Default for RepeatMode {
+    fn default() -> Self {
+        RepeatMode::Ask
+    }
+}
+
 impl MediaList {
+    /// Whether seeing the episode or chapter means a completed entry is being started over
+    pub fn is_repeat_start(&self, episode: f64) -> bool {
+        matches!(self.status, Some(MediaListStatus::Completed)) && episode <= 1.0
+    }
+
+    /// Switches a completed entry to Repeating at the progress
+    pub fn start_repeat(&mut self, progress: i32) {
+        self.status = Some(MediaListStatus::Repeating);
+        self.progress = Some(progress);
+        if self.progress_volumes.is_some() {
+            self.progress_volumes = Some(0);
+        }
+    }
+
+    /// Makes the status change, dates the entry already has are kept unless the rules
+    /// say to overwrite them. A repeat counts up instead of changing the completion date.
+    pub fn apply_status_change(&mut self, change: StatusChange, rules: &StatusRules) {
+        let today = |date: &mut Option<FuzzyDate>| {
+            if rules.overwrite_dates || !FuzzyDate::is_set(date) {
+                *date = Some(FuzzyDate::today_local());
+            }
+        };
+        match change {
+            StatusChange::Start => {
+                self.status = Some(MediaListStatus::Current);
+                today(&mut self.started_at);
+            }
+            StatusChange::Complete => {
+                match self.status {
+                    Some(MediaListStatus::Repeating) => {
+                        self.repeat = Some(self.repeat.unwrap_or_default() + 1);
+                    }
+                    _ => today(&mut self.completed_at),
+                }
+                self.status = Some(MediaListStatus::Completed);
+            }
+        }
+    }
+
+    /// The episodes or chapters of the media, if they're known
+    fn total(&self) -> Option<i32> {
+        let media = self.media.as_ref()?;
+        match media.media_type? {
+            MediaType::Anime => media.episodes,
+            MediaType::Manga => media.chapters,
+        }
+    }
+
+    /// Whether the progress reached the end of the media, None if its total isn't known
+    fn is_finished(&self) -> Option<bool> {
+        Some(self.progress.unwrap_or_default() >= self.total()?)
+    }
+
+    /// The queued `update` of this entry with its progress moved by `change`. The update is
+    /// worked out again from this entry so the status follows the edited progress, and the
+    /// progress stays within the episodes or chapters of the media when they're known.
+    pub fn edit_update(
+        &self,
+        update: &MediaList,
+        change: i32,
+        rules: &StatusRules,
+    ) -> (MediaList, ProgressUpdate) {
+        let mut progress = (update.progress.unwrap_or_default() + change).max(0);
+        if let Some(total) = self.total() {
+            progress = progress.min(total);
+        }
+        let mut edited = self.clone();
+        let repeating =
+            |entry: &MediaList| matches!(entry.status, Some(MediaListStatus::Repeating));
+        if repeating(update) && !repeating(self) {
+            edited.start_repeat(0);
+        }
+        let result = edited.update_progress(Some(progress), None, rules);
+        // Going below the list progress makes no status change
+        edited.progress = Some(progress);
+        edited.progress_volumes = update.progress_volumes;
+        (edited, result)
+    }
+
+    /// `progress` is what the list progress should be, see `recognition::Episode::list_progress`.
+    /// Status changes the rules make automatically are applied, when both the start and the
+    /// completion of the entry need approval only the completion is asked about.
     pub fn update_progress(
         &mut self,
-        progress: Option<f64>,
+        progress: Option<i32>,
         progress_volumes: Option<f64>,
-    ) -> bool {
+        rules: &StatusRules,
+    ) -> ProgressUpdate {
         let media = if let Some(media) = &mut self.media {
             media
         } else {
-            return false;
+            return ProgressUpdate::default();
         };
         let media_type = if let Some(media_type) = &media.media_type {
             media_type
         } else {
-            return false;
+            return ProgressUpdate::default();
         };
         let mut updated = false;
         match media_type {
@@ -550,9 +701,9 @@ impl MediaList {
                 let episodes = match self.progress {
                     Some(episodes) => {
                         if let Some(progress) = progress {
-                            if progress as i32 > episodes {
+                            if progress > episodes {
                                 updated = true;
-                                progress as i32
+                                progress
                             } else {
                                 episodes
                             }
@@ -560,7 +711,7 @@ impl MediaList {
                             episodes
                         }
                     }
-                    None => progress.unwrap_or_default() as i32,
+                    None => progress.unwrap_or_default(),
                 };
                 self.progress = Some(episodes);
             }
@@ -568,9 +719,9 @@ impl MediaList {
                 let chapters = match self.progress {
                     Some(chapters) => {
                         if let Some(progress) = progress {
-                            if progress as i32 > chapters {
+                            if progress > chapters {
                                 updated = true;
-                                progress as i32
+                                progress
                             } else {
                                 chapters
                             }
@@ -578,7 +729,7 @@ impl MediaList {
                             chapters
                         }
                     }
-                    None => progress.unwrap_or_default() as i32,
+                    None => progress.unwrap_or_default(),
                 };
 
                 let volumes = match self.progress_volumes {
@@ -602,53 +753,92 @@ impl MediaList {
             }
         }
 
-        if updated && self.progress.is_some() {
-            let progress = self.progress.unwrap();
-            if progress == 1 && self.status.is_some() {
-                if let Some(status) = &mut self.status {
-                    match status {
-                        MediaListStatus::Current
-                        | MediaListStatus::Planning
-                        | MediaListStatus::Dropped
-                        | MediaListStatus::Paused => {
-                            *status = MediaListStatus::Current;
-                            self.started_at = Some(FuzzyDate::today_local());
-                        }
-                        _ => {}
-                    }
-                }
+        let mut pending = None;
+        if !updated {
+            return ProgressUpdate { updated, pending };
+        }
+        let mut changes = Vec::new();
+        match self.status {
+            Some(MediaListStatus::Planning)
+            | Some(MediaListStatus::Paused)
+            | Some(MediaListStatus::Dropped)
+                if self.progress == Some(1) || rules.start_at_any_progress =>
+            {
+                changes.push((StatusChange::Start, rules.start))
             }
-            if let Some(media) = &mut self.media {
-                if let Some(media_type) = &media.media_type {
-                    match media_type {
-                        MediaType::Anime => {
-                            if let Some(episodes) = media.episodes {
-                                if progress == episodes {
-                                    self.status = Some(MediaListStatus::Completed);
-                                    self.completed_at = Some(FuzzyDate::today_local());
-                                }
-                            }
-                        }
-                        MediaType::Manga => {
-                            if let Some(chapters) = media.chapters {
-                                if progress == chapters {
-                                    self.status = Some(MediaListStatus::Completed);
-                                    self.completed_at = Some(FuzzyDate::today_local());
-                                }
-                            }
-                            if let Some(volumes) = media.volumes {
-                                if progress == volumes {
-                                    self.status = Some(MediaListStatus::Completed);
-                                    self.completed_at = Some(FuzzyDate::today_local());
-                                }
-                            }
-                        }
-                    }
-                }
+            // Only the start date changes, which isn't worth asking about
+            Some(MediaListStatus::Current)
+                if self.progress == Some(1) && rules.start != TransitionMode::Never =>
+            {
+                changes.push((StatusChange::Start, TransitionMode::Automatic))
+            }
+            _ => {}
+        }
+        let complete = match self.is_finished() {
+            Some(true) => rules.complete,
+            Some(false) => TransitionMode::Never,
+            None => rules.unknown_total_mode(self.media.as_ref().and_then(|m| m.status.as_ref())),
+        };
+        changes.push((StatusChange::Complete, complete));
+        for (change, mode) in changes {
+            match mode {
+                TransitionMode::Automatic => self.apply_status_change(change, rules),
+                TransitionMode::Confirm => pending = Some(change),
+                TransitionMode::Never => {}
             }
         }
 
-        updated
+        ProgressUpdate { updated, pending }
+    }
+
+    /// What the entry changes from the entry before, for showing an update to the user
+    pub fn changes_from(&self, before: &MediaList) -> Vec<FieldChange> {
+        let media_type = self
+            .media
+            .as_ref()
+            .and_then(|media| media.media_type)
+            .unwrap_or_default();
+        let status = |status: &Option<MediaListStatus>| match status {
+            Some(status) => status.list_name(media_type).to_string(),
+            None => "-".to_string(),
+        };
+        let mut fields = vec![
+            ("Status", status(&before.status), status(&self.status)),
+            ("Progress", before.progress_string(), self.progress_string()),
+        ];
+        if media_type == MediaType::Manga {
+            fields.push((
+                "Volumes",
+                before.progress_volumes_string(),
+                self.progress_volumes_string(),
+            ));
+        }
+        fields.extend(vec![
+            (
+                "Repeats",
+                before.repeat.unwrap_or_default().to_string(),
+                self.repeat.unwrap_or_default().to_string(),
+            ),
+            (
+                "Started",
+                FuzzyDate::display(&before.started_at),
+                FuzzyDate::display(&self.started_at),
+            ),
+            (
+                "Completed",
+                FuzzyDate::display(&before.completed_at),
+                FuzzyDate::display(&self.completed_at),
+            ),
+        ]);
+        fields
+            .into_iter()
+            .filter(|(_, before, after)| before != after)
+            .map(|(field, before, after)| FieldChange {
+                field,
+                before,
+                after,
+            })
+            .collect()
     }
 
     pub fn progress_string(&self) -> String {
@@ -718,6 +908,14 @@ impl MediaList {
     // }
 }
 
+/// A field a list update changes, with the values shown to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FuzzyDate {
     pub year: Option<i32>,
@@ -726,6 +924,32 @@ pub struct FuzzyDate {
 }
 
 impl FuzzyDate {
+    /// AniList sends dates that were never set with every part null
+    pub fn is_set(date: &Option<FuzzyDate>) -> bool {
+        match date {
+            Some(date) => date.year.is_some(),
+            None => false,
+        }
+    }
+
+    /// The date as year-month-day, leaving out the parts that aren't set
+    pub fn display(date: &Option<FuzzyDate>) -> String {
+        let date = match date {
+            Some(date) if date.year.is_some() => date,
+            _ => return "-".to_string(),
+        };
+        [date.year, date.month, date.day]
+            .iter()
+            .filter_map(|part| *part)
+            .enumerate()
+            .map(|(i, part)| match i {
+                0 => part.to_string(),
+                _ => format!("{:02}", part),
+            })
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+
     pub fn today_local() -> Self {
         let date = Local::today().naive_local();
 
@@ -758,6 +982,7 @@ pub struct Media {
     pub cover_image: Option<MediaCoverImage>,
     pub description: Option<String>,
     pub format: Option<MediaFormat>,
+    pub status: Option<MediaStatus>,
     pub is_licensed: Option<bool>,
     pub relations: Option<MediaConnection>,
     // ...
@@ -833,6 +1058,17 @@ pub enum MediaRelation {
     Contains,
 }
 
+/// Whether the media is still coming out
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaStatus {
+    Finished,
+    Releasing,
+    NotYetReleased,
+    Cancelled,
+    Hiatus,
+}
+
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MediaFormat {
@@ -869,6 +1105,7 @@ impl MediaFormat {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::anilist::UnknownTotal;
 
     #[test]
     fn media_type() {
@@ -897,4 +1134,187 @@ mod tests {
             Err(err) => panic!(err),
         }
     }
+
+    #[test]
+    fn repeats_count_up_when_finished() {
+        let mut entry: MediaList = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "mediaId": 2,
+            "status": "COMPLETED",
+            "progress": 12,
+            "repeat": 0,
+            "completedAt": { "year": 2020, "month": 1, "day": 1 },
+            "media": { "id": 2, "type": "ANIME", "episodes": 12 },
+        }))
+        .unwrap();
+        assert!(entry.is_repeat_start(1.0));
+        let rules = StatusRules::default();
+        assert!(!entry.update_progress(Some(1), None, &rules).updated);
+
+        entry.start_repeat(1);
+        assert!(matches!(entry.status, Some(MediaListStatus::Repeating)));
+        assert!(!entry.is_repeat_start(1.0));
+        assert!(entry.update_progress(Some(12), None, &rules).updated);
+        assert!(matches!(entry.status, Some(MediaListStatus::Completed)));
+        assert_eq!(entry.repeat, Some(1));
+        assert_eq!(
+            entry.completed_at.as_ref().and_then(|date| date.year),
+            Some(2020)
+        );
+    }
+
+    #[test]
+    fn status_rules_decide_transitions() {
+        let entry = |status: &str, media: serde_json::Value| -> MediaList {
+            serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "mediaId": 2,
+                "status": status,
+                "progress": 0,
+                "progressVolumes": 0,
+                "startedAt": { "year": 2019, "month": null, "day": null },
+                "completedAt": { "year": null, "month": null, "day": null },
+                "media": media,
+            }))
+            .unwrap()
+        };
+        let manga = serde_json::json!({
+            "id": 2, "type": "MANGA", "chapters": 50, "volumes": 5, "status": "FINISHED",
+        });
+
+        // Only the first chapter starts an entry unless the rules say otherwise
+        let mut planning = entry("PLANNING", manga.clone());
+        planning.update_progress(Some(5), None, &StatusRules::default());
+        assert!(matches!(planning.status, Some(MediaListStatus::Planning)));
+        let mut planning = entry("PLANNING", manga.clone());
+        planning.update_progress(Some(1), None, &StatusRules::default());
+        assert!(matches!(planning.status, Some(MediaListStatus::Current)));
+
+        // Chapter 5 isn't the end just because there are 5 volumes
+        let any_progress = StatusRules {
+            start_at_any_progress: true,
+            ..StatusRules::default()
+        };
+        let mut planning = entry("PLANNING", manga.clone());
+        let update = planning.update_progress(Some(5), None, &any_progress);
+        assert_eq!(update.pending, None);
+        assert!(matches!(planning.status, Some(MediaListStatus::Current)));
+        assert_eq!(planning.started_at.as_ref().unwrap().year, Some(2019));
+
+        let confirm = StatusRules {
+            complete: TransitionMode::Confirm,
+            ..StatusRules::default()
+        };
+        let mut current = entry("CURRENT", manga);
+        let update = current.update_progress(Some(50), None, &confirm);
+        assert_eq!(update.pending, Some(StatusChange::Complete));
+        assert!(matches!(current.status, Some(MediaListStatus::Current)));
+        current.apply_status_change(StatusChange::Complete, &confirm);
+        assert!(FuzzyDate::is_set(&current.completed_at));
+
+        let releasing = serde_json::json!({ "id": 2, "type": "MANGA", "status": "RELEASING" });
+        let unknown = StatusRules {
+            unknown_total: UnknownTotal::ConfirmWhenFinished,
+            ..StatusRules::default()
+        };
+        let mut current = entry("CURRENT", releasing);
+        assert_eq!(
+            current.update_progress(Some(80), None, &unknown).pending,
+            None
+        );
+        current.media.as_mut().unwrap().status = Some(MediaStatus::Finished);
+        let update = current.update_progress(Some(81), None, &unknown);
+        assert_eq!(update.pending, Some(StatusChange::Complete));
+    }
+
+    #[test]
+    fn edited_updates_follow_the_progress() {
+        let entry: MediaList = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "mediaId": 2,
+            "status": "CURRENT",
+            "progress": 10,
+            "completedAt": { "year": null, "month": null, "day": null },
+            "media": { "id": 2, "type": "ANIME", "episodes": 12 },
+        }))
+        .unwrap();
+        let rules = StatusRules::default();
+        let mut update = entry.clone();
+        update.update_progress(Some(12), None, &rules);
+        assert!(matches!(update.status, Some(MediaListStatus::Completed)));
+
+        let (edited, _) = entry.edit_update(&update, -1, &rules);
+        assert_eq!(edited.progress, Some(11));
+        assert!(matches!(edited.status, Some(MediaListStatus::Current)));
+        assert!(!FuzzyDate::is_set(&edited.completed_at));
+
+        let (edited, _) = entry.edit_update(&update, 1, &rules);
+        assert_eq!(edited.progress, Some(12));
+        assert!(matches!(edited.status, Some(MediaListStatus::Completed)));
+
+        let (edited, _) = entry.edit_update(&entry, -11, &rules);
+        assert_eq!(edited.progress, Some(0));
+    }
+
+    #[test]
+    fn added_entries_go_in_the_list_of_their_status() {
+        let mut collection: MediaListCollection = serde_json::from_value(serde_json::json!({
+            "lists": [
+                { "name": "Watching", "isCustomList": true, "status": null, "entries": [] },
+                { "name": "Completed", "isCustomList": false, "status": "COMPLETED", "entries": [] },
+            ],
+        }))
+        .unwrap();
+        let entry = |id: i32| -> MediaList {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "mediaId": id,
+                "status": "CURRENT",
+                "media": { "id": id, "type": "ANIME" },
+            }))
+            .unwrap()
+        };
+        collection.insert_entry(entry(1), MediaType::Anime);
+        collection.insert_entry(entry(2), MediaType::Anime);
+        let lists = collection.lists.as_ref().unwrap();
+        assert_eq!(lists.len(), 3);
+        let watching = lists[2].as_ref().unwrap();
+        assert_eq!(watching.name.as_deref(), Some("Watching"));
+        assert_eq!(watching.count_entries(), 2);
+        assert!(collection.find_entry_by_id(2).is_some());
+    }
+
+    #[test]
+    fn lists_what_an_update_changes() {
+        let before: MediaList = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "mediaId": 2,
+            "status": "PLANNING",
+            "progress": 0,
+            "startedAt": { "year": null, "month": null, "day": null },
+            "media": { "id": 2, "type": "ANIME", "episodes": 12 },
+        }))
+        .unwrap();
+        let mut after = before.clone();
+        after.status = Some(MediaListStatus::Current);
+        after.progress = Some(3);
+        after.started_at = Some(FuzzyDate {
+            year: Some(2020),
+            month: Some(4),
+            day: Some(1),
+        });
+        let changes: Vec<(&str, String, String)> = after
+            .changes_from(&before)
+            .into_iter()
+            .map(|change| (change.field, change.before, change.after))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("Status", "Planning".to_string(), "Watching".to_string()),
+                ("Progress", "0 / 12".to_string(), "3 / 12".to_string()),
+                ("Started", "-".to_string(), "2020-04-01".to_string()),
+            ]
+        );
+    }
 }
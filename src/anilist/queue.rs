@@ -0,0 +1,945 @@
+use super::{AniListError, FieldChange, MediaList, MediaType};
+use crate::{
+    recognition::Playback,
+    settings::{self, file_path},
+};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter},
+    time::{Duration, Instant},
+};
+
+/// Detections further apart than this break up continuous detection
+const MAX_DETECTION_GAP: Duration = Duration::from_secs(10);
+/// How many sent updates are kept in the history
+const MAX_HISTORY: usize = 20;
+/// Sends of an update that can fail before it's moved to the failed updates
+const MAX_ATTEMPTS: u32 = 5;
+/// The wait before the first retry, doubled for each retry after it
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// What has to happen while a media is detected before its update is sent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchPolicy {
+    /// Seconds the media has to be detected without a break
+    pub min_detection_secs: u64,
+    /// How much of a file has to be played, for players that report it
+    pub min_playback_percentage: f64,
+    /// Time spent paused doesn't count toward the detection time
+    pub ignore_paused: bool,
+}
+
+impl Default for WatchPolicy {
+    fn default() -> Self {
+        WatchPolicy {
+            min_detection_secs: 120,
+            min_playback_percentage: 75.0,
+            ignore_paused: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchPolicies {
+    pub anime: WatchPolicy,
+    pub manga: WatchPolicy,
+}
+
+impl Default for WatchPolicies {
+    fn default() -> Self {
+        WatchPolicies {
+            anime: WatchPolicy::default(),
+            manga: WatchPolicy {
+                min_detection_secs: 60,
+                ..WatchPolicy::default()
+            },
+        }
+    }
+}
+
+impl WatchPolicies {
+    pub fn get(&self, media_type: MediaType) -> WatchPolicy {
+        match media_type {
+            MediaType::Anime => self.anime,
+            MediaType::Manga => self.manga,
+        }
+    }
+}
+
+/// Whether detected updates are sent without the user accepting them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateMode {
+    Automatic,
+    /// Detected updates wait until the user accepts them
+    Confirm,
+    /// Detected updates are only shown, changes the user makes are still sent
+    NotifyOnly,
+}
+
+impl Default for UpdateMode {
+    fn default() -> Self {
+        UpdateMode::Automatic
+    }
+}
+
+/// A queued update shown to the user before it's sent
+#[derive(Debug, Clone)]
+pub struct PendingUpdate {
+    pub media: MediaList,
+    /// What the update changes in the list entry
+    pub changes: Vec<FieldChange>,
+    /// False in notify only mode, where it can only be dismissed
+    pub can_accept: bool,
+}
+
+/// Why a queued update hasn't been sent yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueuedState {
+    /// Sent once the update delay is over
+    Delayed(Duration),
+    /// The media hasn't been watched or read enough yet
+    Watching(WatchProgress),
+    /// Waiting for the user to accept it
+    NeedsApproval,
+    /// Only shown in notify only mode
+    NotSent,
+    /// Left from the last run, checked against the list once it's loaded
+    Restored,
+    /// Sending it failed, it's tried again after the wait
+    Retrying(Duration, u32),
+    /// Sent with the next request
+    Ready,
+}
+
+/// A queued update as shown to the user
+#[derive(Debug, Clone)]
+pub struct QueuedInfo {
+    pub media: MediaList,
+    pub state: QueuedState,
+}
+
+/// An update that was sent, `result` is None until AniList answers
+#[derive(Debug, Clone)]
+pub struct SentUpdate {
+    pub media_id: i32,
+    pub title: String,
+    pub changes: Vec<FieldChange>,
+    pub sent_at: DateTime<Local>,
+    pub result: Option<Result<(), String>>,
+}
+
+/// How sending an update went
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendOutcome {
+    Sent,
+    /// Network and server errors, the update is sent again later
+    Retry(String),
+    /// AniList rejected the update
+    Failed(String),
+}
+
+impl SendOutcome {
+    pub fn from_result(result: &Result<(), AniListError>) -> Self {
+        match result {
+            Ok(()) => SendOutcome::Sent,
+            Err(err) if err.is_retryable() => SendOutcome::Retry(err.to_string()),
+            Err(err) => SendOutcome::Failed(err.to_string()),
+        }
+    }
+}
+
+/// An update that failed for good, kept until the user retries or discards it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedUpdate {
+    pub media: MediaList,
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// A queued update as it's saved between runs
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredUpdate {
+    media: MediaList,
+    approved: bool,
+    attempts: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StoredQueue {
+    updates: Vec<StoredUpdate>,
+    failed: Vec<FailedUpdate>,
+}
+
+/// How long to wait before sending an update again after it failed the number of times
+fn retry_delay(attempts: u32) -> Duration {
+    let delay = RETRY_DELAY * 2u32.saturating_pow(attempts.saturating_sub(1));
+    delay.min(MAX_RETRY_DELAY)
+}
+
+/// How far a detected media is toward its update being sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchProgress {
+    pub detected: Duration,
+    pub required: Duration,
+    /// How far through the file the player is, if it reports it
+    pub percent: Option<f64>,
+    pub required_percent: f64,
+    pub paused: bool,
+}
+
+impl WatchProgress {
+    pub fn is_met(&self) -> bool {
+        let played_enough = match self.percent {
+            Some(percent) => percent >= self.required_percent,
+            None => true,
+        };
+        self.detected >= self.required && played_enough
+    }
+}
+
+#[derive(Debug)]
+struct QueuedUpdate {
+    media: MediaList,
+    queued_at: Instant,
+    playback: Option<Playback>,
+    /// None for changes the user made, which aren't held back
+    policy: Option<WatchPolicy>,
+    detected: Duration,
+    last_seen: Instant,
+    /// Changes the user made and detected updates they accepted
+    approved: bool,
+    /// The user asked for it to be sent without waiting
+    forced: bool,
+    /// Left from the last run and not checked against the list yet
+    restored: bool,
+    /// Times sending it failed
+    attempts: u32,
+    retry_at: Option<Instant>,
+}
+
+impl QueuedUpdate {
+    fn new(media: MediaList, playback: Option<Playback>, policy: Option<WatchPolicy>) -> Self {
+        let now = Instant::now();
+        QueuedUpdate {
+            media,
+            queued_at: now,
+            playback,
+            policy,
+            detected: Duration::default(),
+            last_seen: now,
+            approved: policy.is_none(),
+            forced: false,
+            restored: false,
+            attempts: 0,
+            retry_at: None,
+        }
+    }
+
+    /// An update that was left from the last run or failed to send, the user already saw it
+    fn resend(media: MediaList, approved: bool, attempts: u32) -> Self {
+        QueuedUpdate {
+            approved,
+            attempts,
+            ..QueuedUpdate::new(media, None, None)
+        }
+    }
+
+    /// Whether the update would be sent if the app closed now, so it's worth saving
+    fn is_worth_saving(&self) -> bool {
+        match self.progress() {
+            Some(progress) => progress.is_met(),
+            None => true,
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        matches!(self.playback, Some(Playback { paused: true, .. }))
+    }
+
+    /// The media was detected again, `playback` is what the player reported this time
+    fn observe(&mut self, playback: Option<Playback>) {
+        let now = Instant::now();
+        let gap = now.duration_since(self.last_seen);
+        let ignore_paused = match self.policy {
+            Some(policy) => policy.ignore_paused,
+            None => false,
+        };
+        if gap > MAX_DETECTION_GAP {
+            self.detected = Duration::default();
+        } else if !(ignore_paused && self.is_paused()) {
+            self.detected += gap;
+        }
+        self.last_seen = now;
+        self.playback = playback;
+    }
+
+    fn progress(&self) -> Option<WatchProgress> {
+        let policy = self.policy?;
+        Some(WatchProgress {
+            detected: self.detected,
+            required: Duration::from_secs(policy.min_detection_secs),
+            percent: self.playback.and_then(|playback| playback.percent),
+            required_percent: policy.min_playback_percentage,
+            paused: self.is_paused(),
+        })
+    }
+
+    fn is_ready(&self, update_delay: u64) -> bool {
+        if self.queued_at.elapsed().as_secs() < update_delay {
+            return false;
+        }
+        match self.progress() {
+            Some(progress) => progress.is_met(),
+            None => true,
+        }
+    }
+
+    fn can_send(&self, update_delay: u64, mode: UpdateMode) -> bool {
+        self.state(update_delay, mode) == QueuedState::Ready
+    }
+
+    fn state(&self, update_delay: u64, mode: UpdateMode) -> QueuedState {
+        if self.forced {
+            return QueuedState::Ready;
+        }
+        if self.restored {
+            return QueuedState::Restored;
+        }
+        if let Some(retry_at) = self.retry_at {
+            let now = Instant::now();
+            if retry_at > now {
+                return QueuedState::Retrying(retry_at - now, self.attempts);
+            }
+        }
+        if !self.approved {
+            match mode {
+                UpdateMode::Automatic => {}
+                UpdateMode::Confirm => return QueuedState::NeedsApproval,
+                UpdateMode::NotifyOnly => return QueuedState::NotSent,
+            }
+        }
+        let delay = Duration::from_secs(update_delay);
+        let waited = self.queued_at.elapsed();
+        if waited < delay {
+            return QueuedState::Delayed(delay - waited);
+        }
+        match self.progress() {
+            Some(progress) if !progress.is_met() => QueuedState::Watching(progress),
+            _ => QueuedState::Ready,
+        }
+    }
+
+    /// Detected media that stopped being detected before it was watched enough
+    fn is_abandoned(&self) -> bool {
+        self.policy.is_some() && self.last_seen.elapsed() > MAX_DETECTION_GAP
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ListUpdateQueue {
+    waiting: bool,
+    requests: VecDeque<QueuedUpdate>,
+    /// The update being sent and the times it failed before
+    sending: Option<(MediaList, u32)>,
+    /// Recently sent updates, newest first
+    history: VecDeque<SentUpdate>,
+    failed: Vec<FailedUpdate>,
+    /// What was last written to disk, to skip writing it again
+    saved: String,
+}
+
+impl ListUpdateQueue {
+    const FILE: &'static str = "update_queue.json";
+
+    /// The updates left from the last run, held until they're checked against the list
+    pub fn load() -> Self {
+        let path = match file_path(Self::FILE) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("could not get update queue path: {}", err);
+                return Self::default();
+            }
+        };
+        let stored: StoredQueue = match File::open(&path) {
+            Ok(file) => match serde_json::from_reader(BufReader::new(file)) {
+                Ok(stored) => stored,
+                Err(err) => {
+                    warn!("could not read {:?}: {}", path, err);
+                    return Self::default();
+                }
+            },
+            Err(err) => {
+                debug!("could not open {:?}: {}", path, err);
+                return Self::default();
+            }
+        };
+        let mut queue = Self::from_stored(stored);
+        queue.saved = queue.serialize().unwrap_or_default();
+        queue
+    }
+
+    fn from_stored(stored: StoredQueue) -> Self {
+        let requests = stored
+            .updates
+            .into_iter()
+            .map(|stored| QueuedUpdate {
+                restored: true,
+                ..QueuedUpdate::resend(stored.media, stored.approved, stored.attempts)
+            })
+            .collect();
+        ListUpdateQueue {
+            requests,
+            failed: stored.failed,
+            ..Self::default()
+        }
+    }
+
+    fn serialize(&self) -> Result<String> {
+        let mut updates: Vec<StoredUpdate> = self
+            .requests
+            .iter()
+            .filter(|update| update.is_worth_saving())
+            .map(|update| StoredUpdate {
+                media: update.media.clone(),
+                approved: update.approved,
+                attempts: update.attempts,
+            })
+            .collect();
+        // Whether it went through isn't known if the app closes while it's sent
+        if let Some((media, attempts)) = &self.sending {
+            if self.find_index(media.media_id).is_none() {
+                updates.push(StoredUpdate {
+                    media: media.clone(),
+                    approved: true,
+                    attempts: *attempts,
+                });
+            }
+        }
+        let stored = StoredQueue {
+            updates,
+            failed: self.failed.clone(),
+        };
+        Ok(serde_json::to_string(&stored)?)
+    }
+
+    /// Writes the queue to disk if it changed since it was last written
+    pub fn save(&mut self) -> Result<()> {
+        let serialized = self.serialize()?;
+        if serialized == self.saved {
+            return Ok(());
+        }
+        let path = file_path(Self::FILE)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        std::io::Write::write_all(&mut BufWriter::new(file), serialized.as_bytes())?;
+        self.saved = serialized;
+        Ok(())
+    }
+
+    /// Lets restored updates be sent once the list is loaded, dropping the ones
+    /// `applied` says the list already has
+    pub fn check_restored(&mut self, applied: impl Fn(&MediaList) -> bool) {
+        self.requests.retain(|update| {
+            let keep = !update.restored || !applied(&update.media);
+            if !keep {
+                debug!(
+                    "update of media {} from the last run was already applied",
+                    update.media.media_id
+                );
+            }
+            keep
+        });
+        for update in self.requests.iter_mut() {
+            update.restored = false;
+        }
+    }
+    /// Queues a change the user made, sent after the update delay
+    pub fn enqueue(&mut self, media: MediaList) {
+        match self.find_index(media.media_id) {
+            Some(index) => {
+                let update = &mut self.requests[index];
+                update.media = media;
+                update.policy = None;
+                update.approved = true;
+            }
+            None => self
+                .requests
+                .push_back(QueuedUpdate::new(media, None, None)),
+        }
+    }
+
+    /// Queues an update for detected media, held back until the policy is met
+    pub fn enqueue_detected(
+        &mut self,
+        media: MediaList,
+        playback: Option<Playback>,
+        policy: WatchPolicy,
+    ) {
+        if let Some((sending, _)) = &self.sending {
+            if sending.media_id == media.media_id && media.changes_from(sending).is_empty() {
+                debug!("update of media {} is already being sent", media.media_id);
+                return;
+            }
+        }
+        match self.find_index(media.media_id) {
+            Some(index) => {
+                let update = &mut self.requests[index];
+                update.media = media;
+                update.policy = Some(policy);
+                update.approved = false;
+                update.observe(playback);
+            }
+            None => self
+                .requests
+                .push_back(QueuedUpdate::new(media, playback, Some(policy))),
+        }
+    }
+
+    /// Replaces the media of a queued update, keeping what it's waiting on.
+    /// Returns false if there's no update for the media.
+    pub fn replace(&mut self, media: MediaList) -> bool {
+        match self.find_index(media.media_id) {
+            Some(index) => {
+                self.requests[index].media = media;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The queued update for the media
+    pub fn get(&self, media_id: i32) -> Option<&MediaList> {
+        Some(&self.requests[self.find_index(media_id)?].media)
+    }
+
+    /// Lets a detected update be sent, returns false if there's no update for the media
+    pub fn approve(&mut self, media_id: i32) -> bool {
+        match self.find_index(media_id) {
+            Some(index) => {
+                self.requests[index].approved = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends the update for the media with the next request, whatever it's waiting on
+    pub fn send_now(&mut self, media_id: i32) -> bool {
+        match self.find_index(media_id) {
+            Some(index) => {
+                let update = &mut self.requests[index];
+                update.approved = true;
+                update.forced = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every queued update and what it's waiting on, in the order they were queued
+    pub fn queued(&self) -> Vec<QueuedInfo> {
+        let (update_delay, mode) = {
+            let settings = settings::get_settings().read().unwrap();
+            (settings.update_delay, settings.update_mode)
+        };
+        self.requests
+            .iter()
+            .map(|update| QueuedInfo {
+                media: update.media.clone(),
+                state: update.state(update_delay, mode),
+            })
+            .collect()
+    }
+
+    /// Records an update that's being sent
+    pub fn record_sent(&mut self, media: &MediaList, changes: Vec<FieldChange>) {
+        let title = media
+            .media
+            .as_ref()
+            .and_then(|media| media.preferred_title())
+            .unwrap_or_else(|| media.media_id.to_string());
+        self.history.push_front(SentUpdate {
+            media_id: media.media_id,
+            title,
+            changes,
+            sent_at: Local::now(),
+            result: None,
+        });
+        self.history.truncate(MAX_HISTORY);
+    }
+
+    /// Records how the last update sent for the media went
+    pub fn record_result(&mut self, media_id: i32, result: Result<(), String>) {
+        let sent = self
+            .history
+            .iter_mut()
+            .find(|sent| sent.media_id == media_id && sent.result.is_none());
+        if let Some(sent) = sent {
+            sent.result = Some(result);
+        }
+    }
+
+    pub fn history(&self) -> &VecDeque<SentUpdate> {
+        &self.history
+    }
+
+    /// Whether the update for the media waits on the user in the mode
+    pub fn needs_approval(&self, media_id: i32, mode: UpdateMode) -> bool {
+        match self.find_index(media_id) {
+            Some(index) => mode != UpdateMode::Automatic && !self.requests[index].approved,
+            None => false,
+        }
+    }
+
+    /// Records that the media of a queued update is still detected
+    pub fn observe(&mut self, media_id: i32, playback: Option<Playback>) {
+        if let Some(index) = self.find_index(media_id) {
+            self.requests[index].observe(playback);
+        }
+    }
+
+    /// How far the queued update is toward being sent, None if it isn't held back
+    pub fn progress(&self, media_id: i32) -> Option<WatchProgress> {
+        self.requests[self.find_index(media_id)?].progress()
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn set_waiting(&mut self, waiting: bool) {
+        self.waiting = waiting;
+    }
+
+    pub fn dequeue(&mut self) -> Option<MediaList> {
+        if self.waiting {
+            return None;
+        }
+        let (update_delay, mode) = {
+            let settings = settings::get_settings().read().unwrap();
+            (settings.update_delay, settings.update_mode)
+        };
+        self.dequeue_with(update_delay, mode)
+    }
+
+    fn dequeue_with(&mut self, update_delay: u64, mode: UpdateMode) -> Option<MediaList> {
+        self.requests.retain(|update| {
+            let keep = update.forced || update.is_ready(update_delay) || !update.is_abandoned();
+            if !keep {
+                debug!(
+                    "media {} stopped being detected before it was watched enough",
+                    update.media.media_id
+                );
+            }
+            keep
+        });
+        let index = self
+            .requests
+            .iter()
+            .position(|update| update.can_send(update_delay, mode))?;
+        let update = self.requests.remove(index)?;
+        self.sending = Some((update.media.clone(), update.attempts));
+        Some(update.media)
+    }
+
+    /// Records how sending the last dequeued update went, returning the update if it was sent
+    pub fn finish(&mut self, outcome: SendOutcome) -> Option<MediaList> {
+        let (media, attempts) = self.sending.take()?;
+        let result = match &outcome {
+            SendOutcome::Sent => Ok(()),
+            SendOutcome::Retry(err) | SendOutcome::Failed(err) => Err(err.clone()),
+        };
+        self.record_result(media.media_id, result);
+        let attempts = attempts + 1;
+        let error = match outcome {
+            SendOutcome::Sent => return Some(media),
+            SendOutcome::Retry(_) if self.find_index(media.media_id).is_some() => {
+                debug!(
+                    "not retrying media {}, a newer update replaces it",
+                    media.media_id
+                );
+                return None;
+            }
+            SendOutcome::Retry(err) if attempts < MAX_ATTEMPTS => {
+                let delay = retry_delay(attempts);
+                debug!("retrying media {} in {:?}: {}", media.media_id, delay, err);
+                let mut update = QueuedUpdate::resend(media, true, attempts);
+                update.retry_at = Some(Instant::now() + delay);
+                self.requests.push_back(update);
+                return None;
+            }
+            SendOutcome::Retry(err) | SendOutcome::Failed(err) => err,
+        };
+        warn!("update of media {} failed: {}", media.media_id, error);
+        self.failed
+            .retain(|failed| failed.media.media_id != media.media_id);
+        self.failed.push(FailedUpdate {
+            media,
+            error,
+            attempts,
+        });
+        None
+    }
+
+    pub fn failed(&self) -> &[FailedUpdate] {
+        &self.failed
+    }
+
+    /// Queues a failed update again, returns false if there's no failed update for the media
+    pub fn retry_failed(&mut self, media_id: i32) -> bool {
+        match self
+            .failed
+            .iter()
+            .position(|failed| failed.media.media_id == media_id)
+        {
+            Some(index) => {
+                let failed = self.failed.remove(index);
+                if self.find_index(media_id).is_none() {
+                    self.requests
+                        .push_back(QueuedUpdate::resend(failed.media, true, 0));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn discard_failed(&mut self, media_id: i32) {
+        self.failed
+            .retain(|failed| failed.media.media_id != media_id);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<MediaList> {
+        match self.requests.remove(index) {
+            Some(update) => Some(update.media),
+            None => None,
+        }
+    }
+
+    pub fn find_index(&self, media_id: i32) -> Option<usize> {
+        self.requests
+            .iter()
+            .position(|update| update.media.media_id == media_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(policy: WatchPolicy) -> QueuedUpdate {
+        let media: MediaList = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "mediaId": 2,
+        }))
+        .unwrap();
+        QueuedUpdate::new(media, None, Some(policy))
+    }
+
+    fn playback(percent: f64, paused: bool) -> Option<Playback> {
+        Some(Playback {
+            percent: Some(percent),
+            paused,
+        })
+    }
+
+    #[test]
+    fn held_back_until_the_policy_is_met() {
+        let policy = WatchPolicy {
+            min_detection_secs: 0,
+            min_playback_percentage: 75.0,
+            ignore_paused: true,
+        };
+        let mut update = update(policy);
+        assert!(update.is_ready(0));
+        update.observe(playback(50.0, false));
+        assert!(!update.is_ready(0));
+        update.observe(playback(80.0, false));
+        assert!(update.is_ready(0));
+
+        let mut update = self::update(WatchPolicy {
+            min_detection_secs: 60,
+            ..policy
+        });
+        update.observe(playback(90.0, false));
+        assert!(!update.is_ready(0));
+        update.detected = Duration::from_secs(60);
+        assert!(update.is_ready(0));
+    }
+
+    #[test]
+    fn detected_updates_wait_for_approval() {
+        let mut update = update(WatchPolicy {
+            min_detection_secs: 0,
+            ..WatchPolicy::default()
+        });
+        assert!(update.can_send(0, UpdateMode::Automatic));
+        assert!(!update.can_send(0, UpdateMode::Confirm));
+        assert!(!update.can_send(0, UpdateMode::NotifyOnly));
+        update.approved = true;
+        assert!(update.can_send(0, UpdateMode::Confirm));
+
+        let mut queue = ListUpdateQueue::default();
+        queue.enqueue_detected(update.media.clone(), None, WatchPolicy::default());
+        assert!(queue.needs_approval(2, UpdateMode::Confirm));
+        assert!(!queue.needs_approval(2, UpdateMode::Automatic));
+        assert!(queue.approve(2));
+        assert!(!queue.needs_approval(2, UpdateMode::Confirm));
+    }
+
+    #[test]
+    fn sent_updates_are_kept_with_their_result() {
+        let mut queue = ListUpdateQueue::default();
+        let media = update(WatchPolicy::default()).media;
+        queue.enqueue_detected(media.clone(), None, WatchPolicy::default());
+        assert!(!queue.requests[0].can_send(0, UpdateMode::Confirm));
+        assert!(queue.send_now(2));
+        assert!(queue.requests[0].can_send(0, UpdateMode::Confirm));
+
+        for _ in 0..MAX_HISTORY + 1 {
+            queue.record_sent(&media, Vec::new());
+        }
+        assert_eq!(queue.history().len(), MAX_HISTORY);
+        queue.record_result(2, Err("rate limited".to_string()));
+        queue.record_result(2, Ok(()));
+        assert_eq!(
+            queue.history()[0].result,
+            Some(Err("rate limited".to_string()))
+        );
+        assert_eq!(queue.history()[1].result, Some(Ok(())));
+        assert_eq!(queue.history()[0].title, "2");
+    }
+
+    #[test]
+    fn failed_sends_are_retried_then_kept() {
+        let mut queue = ListUpdateQueue::default();
+        let media = update(WatchPolicy::default()).media;
+        queue.enqueue(media.clone());
+        queue.requests[0].queued_at -= Duration::from_secs(60);
+        let mut attempts = 0;
+        loop {
+            queue.requests[0].retry_at = None;
+            let sent = queue.dequeue_with(0, UpdateMode::Automatic).unwrap();
+            assert_eq!(sent.media_id, 2);
+            assert!(queue
+                .finish(SendOutcome::Retry("timed out".to_string()))
+                .is_none());
+            attempts += 1;
+            if queue.requests.is_empty() {
+                break;
+            }
+            assert!(matches!(
+                queue.requests[0].state(0, UpdateMode::Automatic),
+                QueuedState::Retrying(_, n) if n == attempts
+            ));
+        }
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert_eq!(queue.failed().len(), 1);
+        assert_eq!(retry_delay(1), RETRY_DELAY);
+        assert_eq!(retry_delay(3), RETRY_DELAY * 4);
+        assert_eq!(retry_delay(30), MAX_RETRY_DELAY);
+
+        assert!(queue.retry_failed(2));
+        assert!(queue.failed().is_empty());
+        queue.dequeue_with(0, UpdateMode::Automatic).unwrap();
+        let sent = queue.finish(SendOutcome::Sent).unwrap();
+        assert_eq!(sent.media_id, media.media_id);
+    }
+
+    #[test]
+    fn restored_updates_wait_for_the_list() {
+        let mut queue = ListUpdateQueue::default();
+        queue.enqueue(update(WatchPolicy::default()).media);
+        let mut media = update(WatchPolicy::default()).media;
+        media.media_id = 3;
+        queue.enqueue_detected(media, None, WatchPolicy::default());
+        let mut sending = update(WatchPolicy::default()).media;
+        sending.media_id = 4;
+        queue.sending = Some((sending, 1));
+
+        let stored: StoredQueue = serde_json::from_str(&queue.serialize().unwrap()).unwrap();
+        // Detected media that wasn't watched enough isn't saved, the one being sent is
+        assert_eq!(stored.updates.len(), 2);
+        let mut queue = ListUpdateQueue::from_stored(stored);
+        assert_eq!(
+            queue.requests[0].state(0, UpdateMode::Automatic),
+            QueuedState::Restored
+        );
+        queue.check_restored(|media| media.media_id == 2);
+        assert_eq!(queue.requests.len(), 1);
+        assert_eq!(queue.requests[0].attempts, 1);
+        assert!(queue.requests[0].can_send(0, UpdateMode::Confirm));
+    }
+
+    #[test]
+    fn paused_time_and_gaps_dont_count() {
+        let mut update = update(WatchPolicy::default());
+        update.playback = playback(10.0, true);
+        update.last_seen -= Duration::from_secs(5);
+        update.observe(playback(10.0, false));
+        assert_eq!(update.detected, Duration::default());
+
+        update.last_seen -= Duration::from_secs(5);
+        update.observe(playback(20.0, false));
+        assert!(update.detected >= Duration::from_secs(5));
+        assert!(!update.is_abandoned());
+
+        update.last_seen -= MAX_DETECTION_GAP * 2;
+        assert!(update.is_abandoned());
+        update.observe(playback(30.0, false));
+        assert_eq!(update.detected, Duration::default());
+    }
+
+    #[tokio::test]
+    async fn sends_updates_through_replayed_responses() {
+        let _transport = crate::anilist::transport::tests::replay_fixtures().await;
+        let entry = |id, media_id, progress| -> MediaList {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "mediaId": media_id,
+                "status": "CURRENT",
+                "progress": progress,
+            }))
+            .unwrap()
+        };
+        let token = Some("replay".to_string());
+        let mut queue = ListUpdateQueue::default();
+        queue.enqueue(entry(1, 97986, 5));
+        queue.enqueue(entry(2, 21, 1000));
+
+        let update = queue.dequeue_with(0, UpdateMode::Automatic).unwrap();
+        let result = crate::anilist::update_media(token.clone(), update)
+            .await
+            .map(|_| ());
+        let sent = queue.finish(SendOutcome::from_result(&result)).unwrap();
+        assert_eq!(sent.media_id, 97986);
+
+        // The server error is retried later
+        let update = queue.dequeue_with(0, UpdateMode::Automatic).unwrap();
+        let result = crate::anilist::update_media(token, update)
+            .await
+            .map(|_| ());
+        assert!(matches!(
+            SendOutcome::from_result(&result),
+            SendOutcome::Retry(_)
+        ));
+        assert!(queue.finish(SendOutcome::from_result(&result)).is_none());
+        assert!(matches!(
+            queue.queued()[0].state,
+            QueuedState::Retrying(_, 1)
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{resources::Resources, settings::file_path};
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use std::{fs, io::ErrorKind, str};
+
+/// Episodes a rule covers, an open ended range is written as `13-?`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpisodeRange {
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+impl EpisodeRange {
+    fn parse(s: &str) -> Result<Self> {
+        let number = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|err| anyhow!("bad episode number {:?}: {}", n, err))
+        };
+        match s.find('-') {
+            Some(dash) => {
+                let to = &s[dash + 1..];
+                Ok(EpisodeRange {
+                    from: number(&s[..dash])?,
+                    to: if to.trim() == "?" {
+                        None
+                    } else {
+                        Some(number(to)?)
+                    },
+                })
+            }
+            None => {
+                let n = number(s)?;
+                Ok(EpisodeRange {
+                    from: n,
+                    to: Some(n),
+                })
+            }
+        }
+    }
+
+    pub fn contains(&self, episode: f64) -> bool {
+        let below_end = match self.to {
+            Some(to) => episode < to as f64 + 1.0,
+            None => true,
+        };
+        episode >= self.from as f64 && below_end
+    }
+}
+
+/// Episodes of one media that are really episodes of another
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelationRule {
+    pub source_id: i32,
+    pub source: EpisodeRange,
+    pub destination_id: i32,
+    pub destination: EpisodeRange,
+}
+
+impl RelationRule {
+    pub fn redirect(&self, media_id: i32, episode: f64) -> Option<(i32, f64)> {
+        if media_id != self.source_id || !self.source.contains(episode) {
+            return None;
+        }
+        let episode = self.destination.from as f64 + (episode - self.source.from as f64);
+        Some((self.destination_id, episode))
+    }
+}
+
+/// Episode redirection rules in the anime-relations format:
+///
+/// ```text
+/// ::rules
+/// # Shingeki no Kyojin Season 2
+/// - 16498|?|16498:26-37 -> 25777|?|20958:1-12!
+/// ```
+///
+/// Ids are MyAnimeList, Kitsu and AniList in that order, only the AniList id is used.
+/// `~` in the destination means the same id as the source, `?` is an unknown id.
+/// A trailing `!` also applies the rule to the destination, for sequels that are numbered on from the prequel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Relations {
+    rules: Vec<RelationRule>,
+}
+
+impl Relations {
+    const FILE: &'static str = "anime-relations.txt";
+    const ANILIST_ID: usize = 2;
+
+    /// The bundled rules, with the user's own rules taking precedence
+    pub fn load() -> Result<Self> {
+        let mut relations = match Resources::get(Self::FILE) {
+            Some(file) => Self::parse(str::from_utf8(&file)?)?,
+            None => {
+                warn!("could not open bundled anime relations file");
+                Self::default()
+            }
+        };
+
+        let path = file_path(Self::FILE)?;
+        match fs::read_to_string(&path) {
+            Ok(text) => match Self::parse(&text) {
+                Ok(custom) => relations.prepend(custom),
+                Err(err) => warn!("error parsing {:?}: {}", path, err),
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                debug!("no custom anime relations at {:?}", path);
+            }
+            Err(err) => warn!("could not open {:?}: {}", path, err),
+        }
+        Ok(relations)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut relations = Relations::default();
+        let mut in_rules = false;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("::") {
+                in_rules = line == "::rules";
+                continue;
+            }
+            if !in_rules {
+                continue;
+            }
+            let rule = match line.strip_prefix("- ") {
+                Some(rule) => rule,
+                None => return Err(anyhow!("line {}: expected a rule starting with -", i + 1)),
+            };
+            let rules = Self::parse_rule(rule).map_err(|err| anyhow!("line {}: {}", i + 1, err))?;
+            relations.rules.extend(rules);
+        }
+        Ok(relations)
+    }
+
+    /// Rules without an AniList id on both sides are skipped
+    fn parse_rule(rule: &str) -> Result<Vec<RelationRule>> {
+        let mut sides = rule.split("->");
+        let (source, destination) = match (sides.next(), sides.next(), sides.next()) {
+            (Some(source), Some(destination), None) => (source.trim(), destination.trim()),
+            _ => return Err(anyhow!("expected one -> in {:?}", rule)),
+        };
+        let (destination, also_destination) = match destination.strip_suffix('!') {
+            Some(destination) => (destination, true),
+            None => (destination, false),
+        };
+
+        let (source_ids, source) = Self::parse_side(source)?;
+        let (destination_ids, destination) = Self::parse_side(destination)?;
+        let source_id = match source_ids[Self::ANILIST_ID] {
+            "?" | "~" => return Ok(Vec::new()),
+            id => id
+                .parse::<i32>()
+                .map_err(|err| anyhow!("bad id {:?}: {}", id, err))?,
+        };
+        let destination_id = match destination_ids[Self::ANILIST_ID] {
+            "?" => return Ok(Vec::new()),
+            "~" => source_id,
+            id => id
+                .parse::<i32>()
+                .map_err(|err| anyhow!("bad id {:?}: {}", id, err))?,
+        };
+
+        let rule = RelationRule {
+            source_id,
+            source,
+            destination_id,
+            destination,
+        };
+        let mut rules = vec![rule.clone()];
+        if also_destination && destination_id != source_id {
+            rules.push(RelationRule {
+                source_id: destination_id,
+                ..rule
+            });
+        }
+        Ok(rules)
+    }
+
+    fn parse_side(side: &str) -> Result<(Vec<&str>, EpisodeRange)> {
+        let colon = side
+            .find(':')
+            .ok_or_else(|| anyhow!("expected ids:episodes in {:?}", side))?;
+        let ids: Vec<&str> = side[..colon].split('|').map(|id| id.trim()).collect();
+        if ids.len() != 3 {
+            return Err(anyhow!("expected three ids in {:?}", side));
+        }
+        Ok((ids, EpisodeRange::parse(&side[colon + 1..])?))
+    }
+
+    /// Puts the other rules in front, so they're used over these ones
+    pub fn prepend(&mut self, other: Relations) {
+        let mut rules = other.rules;
+        rules.append(&mut self.rules);
+        self.rules = rules;
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The media and episode the episode of the media really is, if a rule covers it
+    pub fn redirect(&self, media_id: i32, episode: f64) -> Option<(i32, f64)> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.redirect(media_id, episode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELATIONS: &str = "
+::meta
+- version: 1.3.0
+::rules
+# Continuous numbering
+- 16498|?|16498:26-37 -> 25777|?|20958:1-12!
+# Specials
+- 1|2|100:0 -> ~|~|~:13
+- 3|4|200:13-? -> 5|6|201:1-?
+# No AniList id
+- 7|8|?:1-12 -> 9|10|300:1-12
+";
+
+    #[test]
+    fn parses_and_redirects() {
+        let relations = Relations::parse(RELATIONS).unwrap();
+        assert_eq!(relations.len(), 4);
+        assert_eq!(relations.redirect(16498, 26.0), Some((20958, 1.0)));
+        assert_eq!(relations.redirect(16498, 37.0), Some((20958, 12.0)));
+        assert_eq!(relations.redirect(16498, 25.0), None);
+        assert_eq!(relations.redirect(20958, 30.0), Some((20958, 5.0)));
+        assert_eq!(relations.redirect(20958, 5.0), None);
+        assert_eq!(relations.redirect(100, 0.0), Some((100, 13.0)));
+        assert_eq!(relations.redirect(200, 100.5), Some((201, 88.5)));
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let err = Relations::parse("::rules\n- 1|2|3:1-2 => 4|5|6:1-2").unwrap_err();
+        assert!(err.to_string().starts_with("line 2"));
+        assert!(Relations::parse("::rules\n- 1|2:1 -> 4|5|6:1").is_err());
+    }
+
+    #[test]
+    fn bundled_relations_parse() {
+        let file = Resources::get(Relations::FILE).unwrap();
+        Relations::parse(str::from_utf8(&file).unwrap()).unwrap();
+    }
+}
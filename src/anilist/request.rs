@@ -1,16 +1,22 @@
-use super::models::{Media, MediaList, MediaListCollection, MediaType, User};
+use super::{
+    error::{AniListError, QueryError},
+    limiter::Priority,
+    models::{FuzzyDate, Media, MediaList, MediaListCollection, MediaListStatus, MediaType, User},
+    transport::{transport, TransportRequest},
+};
 use crate::{resources::Resources, settings};
-use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
 use reqwest::{Client, StatusCode};
 use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::{json, Map, Value};
-// use std::path::Path;
-use tokio::time;
 
-#[derive(Deserialize, Debug)]
-pub struct QueryError {
-    pub message: Option<String>,
-    pub status: Option<i32>,
+pub type AniListResult<T> = std::result::Result<T, AniListError>;
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+/// The HTTP client every request shares, keeping its connections open
+pub fn client() -> &'static Client {
+    &CLIENT
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,11 +54,48 @@ pub struct SearchPage {
     pub media: Option<Vec<Option<Media>>>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct MediaResponse {
+    pub media: Option<Media>,
+}
+
+/// Reads the data out of a response, or why there is none
+fn parse_response<R>(status: StatusCode, body: &str) -> AniListResult<R>
+where
+    R: DeserializeOwned,
+{
+    // The data is read after the errors, a failed query can return data that doesn't fit R
+    let response: QueryResponse<Value> = match serde_json::from_str(body) {
+        Ok(response) => response,
+        Err(_) if !status.is_success() => {
+            return Err(AniListError::from_status(status.as_u16(), body))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(errors) = response.errors {
+        if !errors.is_empty() {
+            return Err(AniListError::from_errors(errors));
+        }
+    }
+    if !status.is_success() {
+        return Err(AniListError::from_status(status.as_u16(), body));
+    }
+    match response.data {
+        Some(data) if !data.is_null() => Ok(serde_json::from_value(data)?),
+        _ => Err(AniListError::Deserialize(
+            "the response had no data".to_string(),
+        )),
+    }
+}
+
+/// Sends the query through the transport, see `anilist::set_transport`
 pub async fn query_graphql<R>(
     query_str: &str,
     variables: &Option<Map<String, Value>>,
     token: Option<String>,
-) -> Result<QueryResponse<R>>
+    priority: Priority,
+) -> AniListResult<R>
 where
     R: DeserializeOwned,
 {
@@ -63,87 +106,70 @@ where
     };
 
     let token = match token {
-        Some(tok) => {
-            if tok.is_empty() {
-                return Err(anyhow!("Empty token string"));
-            }
-            tok
-        }
-        None => return Err(anyhow!("No token provided")),
+        Some(tok) if !tok.is_empty() => tok,
+        _ => return Err(AniListError::NoToken),
     };
 
-    let max_rate_limit_count = 5;
-    for _ in 0..max_rate_limit_count {
-        let client = Client::new();
-        let resp = client
-            .post("https://graphql.anilist.co")
-            .header("ContentType", "application/json")
-            .header("Authorization", format!("Bearer {}", token))
-            .json(&query)
-            .send()
-            .await?;
-
-        match resp.status() {
-            StatusCode::TOO_MANY_REQUESTS => {
-                let secs;
-                let retry = resp.headers().get("Retry-After");
-                if let Some(val) = retry {
-                    let header = String::from_utf8_lossy(val.as_bytes());
-                    secs = header.parse::<u64>().unwrap_or(60);
-                } else {
-                    secs = 60;
-                }
-                time::delay_for(time::Duration::from_secs(secs)).await;
-            }
-            StatusCode::OK | _ => {
-                let response: QueryResponse<R> = resp.json().await?;
-                return Ok(response);
-            }
-        }
-    }
-
-    Err(anyhow!("Exceeded the maximum rate limit count (5)"))
+    let request = TransportRequest {
+        url: settings::endpoints().graphql,
+        body: query,
+    };
+    let response = transport().send(&request, &token, priority).await?;
+    parse_response(response.status(), &response.body_text())
 }
 
 pub async fn query_from_file<R>(
     path: &str,
     variables: &Option<Map<String, Value>>,
     token: Option<String>,
-) -> Result<QueryResponse<R>>
+    priority: Priority,
+) -> AniListResult<R>
 where
     R: DeserializeOwned,
 {
     let query: String = Resources::get(path).map_or_else(
-        || Err(anyhow!("could not load query from \"{}\"", path)),
+        || {
+            Err(AniListError::Query(format!(
+                "could not load query from \"{}\"",
+                path
+            )))
+        },
         |query| {
             std::str::from_utf8(&*query).map_or_else(
                 |err| {
-                    Err(anyhow!(
+                    Err(AniListError::Query(format!(
                         "failed to covert \"{}\" query to utf8: {}",
-                        path,
-                        err
-                    ))
+                        path, err
+                    )))
                 },
                 |s| Ok(s.to_string()),
             )
         },
     )?;
-    query_graphql(&query, variables, token).await
+    query_graphql(&query, variables, token, priority).await
 }
 
 pub async fn query_media_list(
     token: Option<String>,
     user_id: i32,
     media_type: MediaType,
-) -> Result<QueryResponse<MediaListCollectionResponse>> {
+) -> AniListResult<MediaListCollectionResponse> {
     let variables = json!({
         "id": user_id,
         "type": media_type,
     });
     if let serde_json::Value::Object(variables) = variables {
-        query_from_file("graphql/media_list.gql", &Some(variables), token).await
+        query_from_file(
+            "graphql/media_list.gql",
+            &Some(variables),
+            token,
+            Priority::Background,
+        )
+        .await
     } else {
-        Err(anyhow!("media list query variables was not a json object"))
+        Err(AniListError::Query(
+            "media list query variables was not a json object".to_string(),
+        ))
     }
 }
 
@@ -151,8 +177,8 @@ pub async fn query_media_lists(
     token: Option<String>,
     user_id: i32,
 ) -> (
-    Result<QueryResponse<MediaListCollectionResponse>>,
-    Result<QueryResponse<MediaListCollectionResponse>>,
+    AniListResult<MediaListCollectionResponse>,
+    AniListResult<MediaListCollectionResponse>,
 ) {
     (
         query_media_list(token.clone(), user_id, MediaType::Anime).await,
@@ -160,26 +186,78 @@ pub async fn query_media_lists(
     )
 }
 
-pub async fn query_user(token: Option<String>) -> Result<QueryResponse<ViewerResponse>> {
-    query_from_file("graphql/user.gql", &None, token).await
+pub async fn query_user(token: Option<String>) -> AniListResult<ViewerResponse> {
+    query_from_file("graphql/user.gql", &None, token, Priority::Interactive).await
 }
 
 pub async fn update_media(
     token: Option<String>,
     media: MediaList,
-) -> Result<QueryResponse<SaveMediaListEntryResponse>> {
+) -> AniListResult<SaveMediaListEntryResponse> {
     let variables = json!({
         "id": media.id,
         "status": media.status,
         "progress": media.progress,
         "progressVolumes": media.progress_volumes.unwrap_or_default(),
+        "repeat": media.repeat,
         "startedAt": media.started_at,
         "completedAt": media.completed_at,
     });
     if let serde_json::Value::Object(variables) = variables {
-        query_from_file("graphql/update_media.gql", &Some(variables), token).await
+        query_from_file(
+            "graphql/update_media.gql",
+            &Some(variables),
+            token,
+            Priority::Interactive,
+        )
+        .await
     } else {
-        Err(anyhow!("update media variables was not a json object"))
+        Err(AniListError::Query(
+            "update media variables was not a json object".to_string(),
+        ))
+    }
+}
+
+/// Adds the media to the user's list with the status, started today
+pub async fn add_media(
+    token: Option<String>,
+    media_id: i32,
+    status: MediaListStatus,
+) -> AniListResult<SaveMediaListEntryResponse> {
+    let variables = json!({
+        "mediaId": media_id,
+        "status": status,
+        "startedAt": FuzzyDate::today_local(),
+    });
+    if let serde_json::Value::Object(variables) = variables {
+        query_from_file(
+            "graphql/add_media.gql",
+            &Some(variables),
+            token,
+            Priority::Interactive,
+        )
+        .await
+    } else {
+        Err(AniListError::Query(
+            "add media variables was not a json object".to_string(),
+        ))
+    }
+}
+
+pub async fn query_media_relations(token: Option<String>, id: i32) -> AniListResult<MediaResponse> {
+    let variables = json!({ "id": id });
+    if let serde_json::Value::Object(variables) = variables {
+        query_from_file(
+            "graphql/relations.gql",
+            &Some(variables),
+            token,
+            Priority::Interactive,
+        )
+        .await
+    } else {
+        Err(AniListError::Query(
+            "media relations variables was not a json object".to_string(),
+        ))
     }
 }
 
@@ -187,84 +265,84 @@ pub async fn query_search(
     token: Option<String>,
     search: String,
     media_type: MediaType,
-) -> Result<QueryResponse<SearchResponse>> {
+) -> AniListResult<SearchResponse> {
     let variables = json!({
         "search": search,
         "mediaType": media_type,
     });
     if let serde_json::Value::Object(variables) = variables {
-        query_from_file("graphql/search.gql", &Some(variables), token).await
+        query_from_file(
+            "graphql/search.gql",
+            &Some(variables),
+            token,
+            Priority::Interactive,
+        )
+        .await
     } else {
-        Err(anyhow!("update media variables was not a json object"))
+        Err(AniListError::Query(
+            "update media variables was not a json object".to_string(),
+        ))
     }
 }
 
-use std::collections::VecDeque;
-use std::time::Instant;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        anilist::{MatchDecision, MatchSettings, Matcher},
+        recognition::Normalizer,
+    };
 
-#[derive(Debug, Default)]
-pub struct ListUpdateQueue {
-    waiting: bool,
-    requests: VecDeque<(MediaList, Instant)>,
-}
+    #[test]
+    fn reads_errors_from_responses() {
+        let media: MediaResponse =
+            parse_response(StatusCode::OK, r#"{"data":{"Media":{"id":1}}}"#).unwrap();
+        assert_eq!(media.media.map(|media| media.id), Some(1));
+
+        let failed = parse_response::<SaveMediaListEntryResponse>(
+            StatusCode::BAD_REQUEST,
+            r#"{"data":null,"errors":[{"message":"Invalid token","status":400}]}"#,
+        );
+        assert_eq!(failed.unwrap_err(), AniListError::InvalidToken);
 
-impl ListUpdateQueue {
-    pub fn enqueue(&mut self, media: MediaList) {
-        let mut found = false;
-        for (m, _) in self.requests.iter_mut() {
-            if m.media_id == *&media.media_id {
-                *m = media.clone();
-                found = true;
-                break;
+        let failed = parse_response::<MediaResponse>(StatusCode::BAD_GATEWAY, "<html></html>");
+        assert_eq!(
+            failed.unwrap_err(),
+            AniListError::Http {
+                status: 502,
+                body: "<html></html>".to_string()
             }
-        }
-        if !found {
-            self.requests.push_back((media, Instant::now()));
-        }
-    }
+        );
 
-    pub fn is_waiting(&self) -> bool {
-        self.waiting
+        let failed = parse_response::<MediaResponse>(StatusCode::OK, r#"{"data":null}"#);
+        assert!(matches!(failed, Err(AniListError::Deserialize(_))));
     }
 
-    pub fn set_waiting(&mut self, waiting: bool) {
-        self.waiting = waiting;
-    }
+    #[tokio::test]
+    async fn matches_replayed_search_results() {
+        let _transport = crate::anilist::transport::tests::replay_fixtures().await;
+        let search = query_search(
+            Some("replay".to_string()),
+            "made in abyss".to_string(),
+            MediaType::Anime,
+        )
+        .await
+        .unwrap();
+        let results: Vec<&Media> = search.page.media.iter().flatten().flatten().collect();
+        assert_eq!(results.len(), 3);
 
-    pub fn dequeue(&mut self) -> Option<MediaList> {
-        match self.requests.front() {
-            Some((_, earlier)) => {
-                let update_delay = settings::get_settings().read().unwrap().update_delay;
-                let elapsed = Instant::now().duration_since(*earlier);
-                if elapsed.as_secs() >= update_delay && !self.waiting {
-                    let front = self.requests.pop_front();
-                    match front {
-                        Some((media, _)) => Some(media),
-                        None => None,
-                    }
-                } else {
-                    None
-                }
-            }
-            None => None,
+        let matcher = Matcher::new(MatchSettings::default(), Normalizer::default());
+        match matcher.decide(matcher.rank(&results, "Made in Abyss"), Some(1.0)) {
+            MatchDecision::Accept(candidate) => assert_eq!(candidate.media_id, 97986),
+            decision => panic!("expected a match, got {:?}", decision),
         }
-    }
-
-    pub fn remove(&mut self, index: usize) -> Option<MediaList> {
-        match self.requests.remove(index) {
-            Some((media, _)) => Some(media),
-            None => None,
-        }
-    }
 
-    pub fn find_index(&self, media_id: i32) -> Option<usize> {
-        let mut i = 0;
-        for (media, _) in self.requests.iter() {
-            if media.media_id == media_id {
-                return Some(i);
-            }
-            i += 1;
-        }
-        None
+        let unrecorded = query_search(
+            Some("replay".to_string()),
+            "unrecorded".to_string(),
+            MediaType::Anime,
+        )
+        .await;
+        assert!(matches!(unrecorded, Err(AniListError::Transport(_))));
     }
 }
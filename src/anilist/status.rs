@@ -0,0 +1,91 @@
+use super::MediaStatus;
+use serde::{Deserialize, Serialize};
+
+/// Whether a status change is made on its own
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionMode {
+    Automatic,
+    /// Ask the user before making the change
+    Confirm,
+    Never,
+}
+
+impl Default for TransitionMode {
+    fn default() -> Self {
+        TransitionMode::Automatic
+    }
+}
+
+/// What happens to media without a known total, like a releasing manga
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownTotal {
+    /// Never complete it, the list has to be changed by hand
+    Ignore,
+    /// Ask whether it's complete once AniList lists it as finished
+    ConfirmWhenFinished,
+}
+
+impl Default for UnknownTotal {
+    fn default() -> Self {
+        UnknownTotal::Ignore
+    }
+}
+
+/// The status changes made to a list entry as its progress goes up
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusRules {
+    /// Planning, paused and dropped entries becoming Current
+    pub start: TransitionMode,
+    /// Start entries at any progress instead of only at the first episode or chapter
+    pub start_at_any_progress: bool,
+    /// Entries becoming Completed at their last episode or chapter
+    pub complete: TransitionMode,
+    /// Replace start and completion dates the entry already has
+    pub overwrite_dates: bool,
+    pub unknown_total: UnknownTotal,
+}
+
+impl Default for StatusRules {
+    fn default() -> Self {
+        StatusRules {
+            start: TransitionMode::Automatic,
+            start_at_any_progress: false,
+            complete: TransitionMode::Automatic,
+            overwrite_dates: false,
+            unknown_total: UnknownTotal::Ignore,
+        }
+    }
+}
+
+impl StatusRules {
+    /// How an entry without a known total is completed, `status` is the media's release status
+    pub fn unknown_total_mode(&self, status: Option<&MediaStatus>) -> TransitionMode {
+        match (self.complete, self.unknown_total, status) {
+            (TransitionMode::Never, _, _) => TransitionMode::Never,
+            (_, UnknownTotal::ConfirmWhenFinished, Some(MediaStatus::Finished)) => {
+                TransitionMode::Confirm
+            }
+            _ => TransitionMode::Never,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusChange {
+    /// To Current, setting the start date
+    Start,
+    /// To Completed, setting the completion date or counting up a repeat
+    Complete,
+}
+
+/// What `MediaList::update_progress` changed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ProgressUpdate {
+    /// Whether the progress went up
+    pub updated: bool,
+    /// A status change the rules want the user to approve first
+    pub pending: Option<StatusChange>,
+}
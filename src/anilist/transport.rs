@@ -0,0 +1,345 @@
+use super::{
+    error::AniListError,
+    limiter::{Priority, LIMITER},
+    request::{client, AniListResult},
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+static TRANSPORT: Lazy<RwLock<Arc<dyn Transport>>> =
+    Lazy::new(|| RwLock::new(Arc::new(LiveTransport)));
+
+/// How many 429 responses a request gets before it gives up
+const MAX_RATE_LIMITED: usize = 5;
+
+/// The response headers kept in cassettes, the rest don't change how a response is handled
+const KEPT_HEADERS: [&str; 4] = [
+    "retry-after",
+    "x-ratelimit-limit",
+    "x-ratelimit-remaining",
+    "x-ratelimit-reset",
+];
+
+/// A GraphQL request to AniList, the token is left out so it's never written to a cassette
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransportRequest {
+    pub url: String,
+    /// The query and its variables
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransportResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Kept as JSON when it is JSON so cassettes stay readable
+    pub body: Value,
+}
+
+impl TransportResponse {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn body_text(&self) -> String {
+        match &self.body {
+            Value::String(text) => text.clone(),
+            body => body.to_string(),
+        }
+    }
+}
+
+/// What sends requests to AniList, see `TransportMode` for the ones there are
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    async fn send(
+        &self,
+        request: &TransportRequest,
+        token: &str,
+        priority: Priority,
+    ) -> AniListResult<TransportResponse>;
+}
+
+/// Sends requests over the network, paced by the rate limiter
+#[derive(Debug)]
+pub struct LiveTransport;
+
+impl LiveTransport {
+    async fn send_once(
+        &self,
+        request: &TransportRequest,
+        token: &str,
+        priority: Priority,
+    ) -> AniListResult<TransportResponse> {
+        LIMITER.acquire(priority).await;
+        let resp = client()
+            .post(&request.url)
+            .header("ContentType", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&request.body)
+            .send()
+            .await?;
+        LIMITER.observe(resp.status(), resp.headers());
+
+        let status = resp.status().as_u16();
+        let headers = KEPT_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = resp.headers().get(*name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        let text = resp.text().await?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        Ok(TransportResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for LiveTransport {
+    /// Sends the request again after a 429, the limiter holds it back until the limit resets
+    async fn send(
+        &self,
+        request: &TransportRequest,
+        token: &str,
+        priority: Priority,
+    ) -> AniListResult<TransportResponse> {
+        let mut retry_after = 60;
+        for _ in 0..MAX_RATE_LIMITED {
+            let response = self.send_once(request, token, priority).await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
+            retry_after = response
+                .header("Retry-After")
+                .and_then(|val| val.parse::<u64>().ok())
+                .unwrap_or(60);
+            warn!("rate limited by AniList, waiting {}s", retry_after);
+        }
+
+        Err(AniListError::RateLimited { retry_after })
+    }
+}
+
+/// A request and the response it got, one per file in a cassette directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Cassette {
+    request: TransportRequest,
+    response: TransportResponse,
+}
+
+/// The file name a request is recorded to, stable between runs and Rust versions
+fn cassette_name(request: &TransportRequest) -> String {
+    // FNV-1a, the keys of serde_json objects are sorted so equal requests serialize the same
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in request.body.to_string().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}.json", hash)
+}
+
+/// Sends requests with another transport, writing each request and response to the directory
+#[derive(Debug)]
+pub struct RecordTransport {
+    dir: PathBuf,
+    inner: Arc<dyn Transport>,
+}
+
+impl RecordTransport {
+    pub fn new(dir: PathBuf, inner: Arc<dyn Transport>) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(RecordTransport { dir, inner })
+    }
+
+    fn write(&self, cassette: &Cassette) -> Result<()> {
+        let path = self.dir.join(cassette_name(&cassette.request));
+        let writer = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer_pretty(writer, cassette)?;
+        debug!("recorded {:?}", path);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for RecordTransport {
+    async fn send(
+        &self,
+        request: &TransportRequest,
+        token: &str,
+        priority: Priority,
+    ) -> AniListResult<TransportResponse> {
+        let response = self.inner.send(request, token, priority).await?;
+        let cassette = Cassette {
+            request: request.clone(),
+            response,
+        };
+        if let Err(err) = self.write(&cassette) {
+            warn!("could not record a cassette to {:?}: {}", self.dir, err);
+        }
+        Ok(cassette.response)
+    }
+}
+
+/// Answers requests with the responses recorded in a directory, without touching the network
+#[derive(Debug)]
+pub struct ReplayTransport {
+    cassettes: Vec<Cassette>,
+}
+
+impl ReplayTransport {
+    /// Reads every cassette in the directory, recorded or written by hand
+    pub fn load(dir: PathBuf) -> Result<Self> {
+        let mut cassettes = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let rdr = BufReader::new(File::open(&path)?);
+            let cassette: Cassette = serde_json::from_reader(rdr)
+                .map_err(|err| anyhow!("invalid cassette {:?}: {}", path, err))?;
+            cassettes.push(cassette);
+        }
+        info!("loaded {} cassettes from {:?}", cassettes.len(), dir);
+        Ok(ReplayTransport { cassettes })
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn send(
+        &self,
+        request: &TransportRequest,
+        _token: &str,
+        _priority: Priority,
+    ) -> AniListResult<TransportResponse> {
+        // Requests are matched on their query and variables, the url only says where it went
+        self.cassettes
+            .iter()
+            .find(|cassette| cassette.request.body == request.body)
+            .map(|cassette| cassette.response.clone())
+            .ok_or_else(|| {
+                AniListError::Transport(format!(
+                    "no recorded response for {} ({})",
+                    cassette_name(request),
+                    request.body["variables"]
+                ))
+            })
+    }
+}
+
+/// Where AniList requests are answered from
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportMode {
+    Live,
+    /// Live, saving every response to cassettes in the directory
+    Record(PathBuf),
+    /// Only the cassettes in the directory, for tests and demos
+    Replay(PathBuf),
+}
+
+impl TransportMode {
+    /// The mode the FUBUKI_REPLAY or FUBUKI_RECORD directory asks for, if any
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|dir| !dir.is_empty());
+        match (var("FUBUKI_REPLAY"), var("FUBUKI_RECORD")) {
+            (Some(dir), _) => Some(TransportMode::Replay(dir.into())),
+            (None, Some(dir)) => Some(TransportMode::Record(dir.into())),
+            (None, None) => None,
+        }
+    }
+
+    pub fn transport(self) -> Result<Arc<dyn Transport>> {
+        Ok(match self {
+            TransportMode::Live => Arc::new(LiveTransport),
+            TransportMode::Record(dir) => {
+                Arc::new(RecordTransport::new(dir, Arc::new(LiveTransport))?)
+            }
+            TransportMode::Replay(dir) => Arc::new(ReplayTransport::load(dir)?),
+        })
+    }
+}
+
+/// The transport every request goes through
+pub fn transport() -> Arc<dyn Transport> {
+    TRANSPORT.read().unwrap().clone()
+}
+
+pub fn set_transport(transport: Arc<dyn Transport>) {
+    info!("answering AniList requests with {:?}", transport);
+    *TRANSPORT.write().unwrap() = transport;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::sync::{Mutex, MutexGuard};
+
+    /// Held by every test that sends requests through `TRANSPORT` so none swaps it under another
+    static TRANSPORT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    /// Answers requests with the cassettes the tests of requests replay, until the guard is dropped
+    pub(crate) async fn replay_fixtures() -> MutexGuard<'static, ()> {
+        let guard = TRANSPORT_LOCK.lock().await;
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cassettes");
+        set_transport(TransportMode::Replay(dir.into()).transport().unwrap());
+        guard
+    }
+
+    #[tokio::test]
+    async fn replays_what_was_recorded() {
+        let dir = std::env::temp_dir().join(format!("fubuki-cassettes-{}", std::process::id()));
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cassettes");
+        let inner = Arc::new(ReplayTransport::load(fixtures.into()).unwrap());
+        let recorder = RecordTransport::new(dir.clone(), inner.clone()).unwrap();
+        let request = inner.cassettes[0].request.clone();
+        let recorded = recorder
+            .send(&request, "secret-token", Priority::Interactive)
+            .await
+            .unwrap();
+
+        let replay = ReplayTransport::load(dir.clone()).unwrap();
+        let replayed = replay
+            .send(&request, "", Priority::Background)
+            .await
+            .unwrap();
+        assert_eq!(replayed, recorded);
+        let written = fs::read_to_string(dir.join(cassette_name(&request))).unwrap();
+        assert!(!written.contains("secret-token"));
+
+        let unknown = TransportRequest {
+            url: request.url,
+            body: serde_json::json!({ "query": "{ Viewer { id } }" }),
+        };
+        assert!(matches!(
+            replay.send(&unknown, "", Priority::Interactive).await,
+            Err(AniListError::Transport(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -42,23 +42,16 @@ pub struct App {
 impl App {
     fn query_user(token: String) -> Command<Message> {
         Command::perform(anilist::query_user(Some(token)), |result| match result {
-            Ok(resp) => {
-                if let Some(viewer_resp) = resp.data {
-                    if let Some(user) = viewer_resp.viewer {
-                        return UserFound(user).into();
-                    }
-                }
-                NoMessage.into()
-            }
-            Err(err) => {
-                error!("anilist user query failed: {}", err);
-                NoMessage.into()
-            }
+            Ok(resp) => match resp.viewer {
+                Some(user) => UserFound(user).into(),
+                None => NoMessage.into(),
+            },
+            Err(err) => RequestFailed("Could not get your AniList profile", err).into(),
         })
     }
 
-    pub fn auth() -> Command<Message> {
-        Command::perform(anilist::auth(), |result| match result {
+    pub fn auth(flow: anilist::AuthFlow) -> Command<Message> {
+        Command::perform(anilist::auth(flow), |result| match result {
             Ok(token) => Authorized(token).into(),
             Err(err) => {
                 error!("authorization failed: {}", err);
@@ -71,57 +64,376 @@ impl App {
         Command::perform(
             anilist::query_media_lists(Some(token), user_id),
             |(anime_result, manga_result)| {
+                let mut error = None;
                 let anime_list = match anime_result {
-                    Ok(resp) => match resp.data {
-                        Some(data) => data.media_list_collection,
-                        None => None,
-                    },
+                    Ok(resp) => resp.media_list_collection,
                     Err(err) => {
                         warn!("anime list query error: {}", err);
+                        error = Some(err);
                         None
                     }
                 };
                 let manga_list = match manga_result {
-                    Ok(resp) => match resp.data {
-                        Some(data) => data.media_list_collection,
-                        None => None,
-                    },
+                    Ok(resp) => resp.media_list_collection,
                     Err(err) => {
                         warn!("manga list query error: {}", err);
+                        error = error.take().or(Some(err));
                         None
                     }
                 };
                 ListRetrieved {
                     anime_list,
                     manga_list,
+                    error,
                 }
                 .into()
             },
         )
     }
 
+    /// Searches for the recognized media, `choose` asks the user to pick from the results
     pub fn query_search(
         token: String,
         recognized: recognition::Media,
         oneshot: bool,
+        choose: bool,
     ) -> Command<Message> {
         Command::perform(
-            anilist::query_search(Some(token), recognized.title, recognized.media_type),
+            anilist::query_search(
+                Some(token),
+                recognition::normalize_title(&recognized.title).query,
+                recognized.media_type,
+            ),
             move |result| match result {
-                Ok(resp) => match resp.data {
-                    Some(search_resp) => match search_resp.page.media {
-                        Some(results) => SearchResults(results, oneshot).into(),
-                        None => NoMessage.into(),
-                    },
+                Ok(resp) => match resp.page.media {
+                    Some(results) => SearchResults(results, oneshot, choose).into(),
                     None => NoMessage.into(),
                 },
-                Err(err) => {
-                    warn!("anilist media search error: {}", err);
+                Err(err) => RequestFailed("Could not search AniList", err).into(),
+            },
+        )
+    }
+
+    /// Tells the user why a request to AniList failed, logging out if the token was rejected
+    fn request_failed(
+        &mut self,
+        what: &str,
+        err: &anilist::AniListError,
+    ) -> Option<Command<Message>> {
+        warn!("{}: {}", what, err);
+        self.page.set_error(format!("{}: {}", what, err));
+        if err.needs_login() {
+            Some(forward_message(Logout.into()))
+        } else {
+            None
+        }
+    }
+
+    /// The entry in the user's list the update is for
+    fn list_entry(&self, update: &anilist::MediaList) -> Option<&anilist::MediaList> {
+        let list = match update.media.as_ref()?.media_type? {
+            anilist::MediaType::Anime => self.page.anime.get_list(),
+            anilist::MediaType::Manga => self.page.manga.get_list(),
+        }?;
+        list.find_entry_by_id(update.media_id)
+    }
+
+    /// Shows the queued updates, and the one of the current media while it waits on the user
+    fn refresh_updates(&mut self) {
+        let mode = settings::get_settings().read().unwrap().update_mode;
+        let changes = |update: &anilist::MediaList| match self.list_entry(update) {
+            Some(before) => update.changes_from(before),
+            None => Vec::new(),
+        };
+        let pending = match &self.media {
+            Some(media) if self.updates.needs_approval(media.media_id, mode) => self
+                .updates
+                .get(media.media_id)
+                .map(|update| anilist::PendingUpdate {
+                    media: update.clone(),
+                    changes: changes(update),
+                    can_accept: mode == anilist::UpdateMode::Confirm,
+                }),
+            _ => None,
+        };
+        let queued = self
+            .updates
+            .queued()
+            .into_iter()
+            .map(|info| {
+                let changes = changes(&info.media);
+                (info, changes)
+            })
+            .collect();
+        let history = self.updates.history().iter().cloned().collect();
+        let failed = self.updates.failed().to_vec();
+        self.page.current_media.set_pending_update(pending);
+        self.page.updates.set_updates(queued, history, failed);
+    }
+
+    /// Adds the media to the user's list as current, then matches the recognized media to it
+    pub fn add_to_list(
+        token: String,
+        media_id: i32,
+        recognized: recognition::Media,
+    ) -> Command<Message> {
+        Command::perform(
+            anilist::add_media(Some(token), media_id, anilist::MediaListStatus::Current),
+            move |result| match result.map(|resp| resp.save_media_list_entry) {
+                Ok(Some(entry)) => EntryAdded(entry, recognized.clone()).into(),
+                Ok(None) => {
+                    warn!("no list entry was returned adding media {}", media_id);
                     NoMessage.into()
                 }
+                Err(err) => RequestFailed("Could not add the media to your list", err).into(),
             },
         )
     }
+
+    /// Queues the update for the media the recognized media was matched to
+    pub fn media_matched(
+        &mut self,
+        id: i32,
+        mut recognized: recognition::Media,
+    ) -> Option<Command<Message>> {
+        // Known redirections come before guessing the offset from the prequels
+        let redirect = match (recognized.media_type, recognized.progress) {
+            (anilist::MediaType::Anime, Some(progress)) => {
+                let settings = settings::get_settings().read().unwrap();
+                settings.relations.redirect(id, progress)
+            }
+            _ => None,
+        };
+        if let Some((redirected_id, episode)) = redirect {
+            debug!(
+                "redirected media {} episode {:?} to media {} episode {}",
+                id, recognized.progress, redirected_id, episode
+            );
+            recognized.set_progress(Some(episode));
+            return self.queue_matched(redirected_id, recognized, None);
+        }
+
+        // Episodes past the end of the media are looked up in the franchise's relations
+        if let Some(episode) = self.episode_past_end(id, &recognized) {
+            let token = settings::get_settings()
+                .read()
+                .unwrap()
+                .anilist
+                .token()
+                .clone();
+            if token.is_some() {
+                return Some(Command::perform(
+                    anilist::resolve_episode(token, id, episode),
+                    move |result| {
+                        let resolved = match result {
+                            Ok(resolved) => resolved,
+                            Err(err) => {
+                                warn!(
+                                    "could not resolve episode {} of media {}: {}",
+                                    episode, id, err
+                                );
+                                None
+                            }
+                        };
+                        EpisodeResolved(id, recognized.clone(), resolved).into()
+                    },
+                ));
+            }
+        }
+        self.offset_matched(id, recognized)
+    }
+
+    /// The recognized episode if it's past the last episode of the media in the user's list
+    fn episode_past_end(&self, id: i32, recognized: &recognition::Media) -> Option<f64> {
+        let episode = match recognized.media_type {
+            anilist::MediaType::Anime => recognized.progress?,
+            anilist::MediaType::Manga => return None,
+        };
+        let entry = self.page.anime.get_list()?.find_entry_by_id(id)?;
+        let episodes = entry.media.as_ref()?.episodes?;
+        if episode > episodes as f64 {
+            Some(episode)
+        } else {
+            None
+        }
+    }
+
+    /// Guesses the offset from the prequels in the user's list
+    fn offset_matched(
+        &mut self,
+        mut id: i32,
+        recognized: recognition::Media,
+    ) -> Option<Command<Message>> {
+        let progress = {
+            let list = match recognized.media_type {
+                anilist::MediaType::Anime => self.page.anime.get_list(),
+                anilist::MediaType::Manga => self.page.manga.get_list(),
+            };
+            let progress = match recognized.media_type {
+                anilist::MediaType::Anime => {
+                    match list {
+                        Some(list) => match recognized.progress {
+                            Some(new_progress) => {
+                                let mut offset_progress =
+                                    list.compute_progress_offset_by_id(id, new_progress as i32);
+
+                                if let Some(offset) = offset_progress {
+                                    if offset < 0 {
+                                        // try to find offset for immediate sequel
+                                        let sequel_offset = list
+                                            .compute_progress_offset_for_sequel(
+                                                id,
+                                                new_progress as i32,
+                                            );
+                                        match sequel_offset {
+                                            Some((offset, sequel_id)) => {
+                                                offset_progress = Some(offset);
+                                                id = sequel_id
+                                            }
+                                            None => {}
+                                        }
+                                    }
+                                }
+
+                                offset_progress
+                            }
+                            None => None,
+                        },
+                        None => None,
+                    }
+                }
+                _ => None,
+            };
+
+            progress
+        };
+        self.queue_matched(id, recognized, progress)
+    }
+
+    /// Shows the matched media, `progress` is the offset progress if one was guessed
+    pub fn queue_matched(
+        &mut self,
+        mut id: i32,
+        mut recognized: recognition::Media,
+        mut progress: Option<i32>,
+    ) -> Option<Command<Message>> {
+        let (fractional, repeat_mode, rules) = {
+            let settings = settings::get_settings().read().unwrap();
+            (
+                settings.fractional_episodes,
+                settings.repeat_mode,
+                settings.status_rules,
+            )
+        };
+        let list = match recognized.media_type {
+            anilist::MediaType::Anime => self.page.anime.get_list_mut(),
+            anilist::MediaType::Manga => self.page.manga.get_list_mut(),
+        }?;
+
+        let is_fractional = match recognized.episode() {
+            Some(episode) => episode.is_fractional(),
+            None => false,
+        };
+        if is_fractional && fractional == recognition::FractionalEpisodes::Special {
+            match list.find_special_entry(id) {
+                Some(special_id) => {
+                    debug!(
+                        "counting episode {:?} of media {} as special {}",
+                        recognized.progress, id, special_id
+                    );
+                    id = special_id;
+                    progress = None;
+                    recognized.set_progress(Some(1.0));
+                }
+                None => debug!("no special of media {} in the list", id),
+            }
+        }
+
+        let entry = list.find_entry_by_id_mut(id);
+        match entry {
+            Some(media) => {
+                // Check if the detected progress is larger than the media's maximum number of episodes/chapters
+                // This is most likely an nth season where the count rolled over
+                if let Some(progress) = progress {
+                    if let Some(recognized_progress) = recognized.progress {
+                        if progress > 0 && progress < recognized_progress as i32 {
+                            let offset = recognition::Episode::new(recognized_progress)
+                                .offset_to(progress)
+                                .number;
+                            debug!(
+                                "offset progress of media {} to {} instead of {}",
+                                media.media_id, offset, recognized_progress
+                            );
+                            recognized.set_progress(Some(offset));
+                        } else {
+                            recognized.set_progress(None);
+                            warn!(
+                                "detected progress offset error for media {}, {} became {}",
+                                media.media_id, recognized_progress, progress
+                            );
+                        }
+                    } else {
+                        warn!("no recognized progress from {:#?}", recognized);
+                    }
+                } else {
+                    debug!("progress offset was None for media id {}", media.media_id);
+                }
+
+                // Clone the media so we only mutate the entry in the user's list
+                // when the request is going to be sent, since the update can be cancelled
+                let mut media_copy = media.clone();
+                let list_progress = recognized
+                    .episode()
+                    .and_then(|episode| episode.list_progress(fractional));
+                let starts_repeat = match (recognized.progress, list_progress) {
+                    (Some(episode), Some(_)) => media.is_repeat_start(episode),
+                    _ => false,
+                };
+                if let (true, Some(list_progress)) = (starts_repeat, list_progress) {
+                    match repeat_mode {
+                        anilist::RepeatMode::Always => {
+                            debug!("repeating media {}", media.media_id);
+                            media_copy.start_repeat(list_progress);
+                            return Some(forward_message(
+                                MediaFound(media_copy, recognized, true).into(),
+                            ));
+                        }
+                        anilist::RepeatMode::Ask => {
+                            media_copy.start_repeat(list_progress);
+                            return Some(Command::batch(vec![
+                                forward_message(
+                                    MediaFound(media.clone(), recognized, false).into(),
+                                ),
+                                forward_message(AskRepeat(media_copy).into()),
+                            ]));
+                        }
+                        anilist::RepeatMode::Never => {}
+                    }
+                }
+                let update =
+                    media_copy.update_progress(list_progress, recognized.progress_volumes, &rules);
+                match update.pending {
+                    Some(change) => {
+                        let mut approved = media_copy.clone();
+                        approved.apply_status_change(change, &rules);
+                        Some(Command::batch(vec![
+                            forward_message(
+                                MediaFound(media_copy, recognized, update.updated).into(),
+                            ),
+                            forward_message(AskStatusChange(approved, change).into()),
+                        ]))
+                    }
+                    None => Some(forward_message(
+                        MediaFound(media_copy, recognized, update.updated).into(),
+                    )),
+                }
+            }
+            None => {
+                debug!("could not find media in list");
+                Some(forward_message(MediaNotFound.into()))
+            }
+        }
+    }
 }
 
 impl Application for App {
@@ -138,7 +450,7 @@ impl Application for App {
             nav: components::Nav::new(),
             page: components::PageContainer::default(),
             user: None,
-            updates: anilist::ListUpdateQueue::default(),
+            updates: anilist::ListUpdateQueue::load(),
         };
         let command = match settings::get_settings().write().unwrap().anilist.token() {
             Some(token) => {
@@ -159,7 +471,6 @@ impl Application for App {
         if let Some(msg) = message.handle(self) {
             commands.push(msg);
         }
-
         if !self.updates.is_waiting() {
             if let Some(media_update) = self.updates.dequeue() {
                 self.updates.set_waiting(true);
@@ -175,35 +486,26 @@ impl Application for App {
                     let settings = settings::get_settings().read().unwrap();
                     settings.anilist.token().clone()
                 };
-                if let Some(media) = &media_update.media {
-                    if let Some(fmt) = &media.media_type {
-                        let list = match fmt {
-                            anilist::MediaType::Anime => self.page.anime.get_list_mut(),
-                            anilist::MediaType::Manga => self.page.manga.get_list_mut(),
-                        };
-                        if let Some(list) = list {
-                            let entry = list.find_entry_by_id_mut(media_update.media_id);
-                            if let Some(entry) = entry {
-                                *entry = media_update.clone();
-                            }
-                        }
-                    }
-                }
+                let changes = match self.list_entry(&media_update) {
+                    Some(before) => media_update.changes_from(before),
+                    None => Vec::new(),
+                };
+                self.updates.record_sent(&media_update, changes);
                 commands.push(Command::perform(
                     anilist::update_media(token, media_update),
-                    |result| match result {
-                        Ok(resp) => {
+                    |result| {
+                        let result = result.map(|resp| {
                             info!("media update succeeded: {:#?}", resp);
-                            MediaUpdateComplete.into()
-                        }
-                        Err(err) => {
-                            warn!("media update failed: {}", err);
-                            MediaUpdateComplete.into()
-                        }
+                        });
+                        MediaUpdateComplete(result).into()
                     },
                 ));
             }
         }
+        if let Err(err) = self.updates.save() {
+            warn!("could not save update queue: {}", err);
+        }
+        self.refresh_updates();
         Command::batch(commands)
     }
 
@@ -238,10 +540,13 @@ impl Application for App {
 }
 
 use ui::components::{
-    nav::{AnimeListPress, CurrentMediaPress, MangaListPress, SettingsPress},
+    nav::{AnimeListPress, CurrentMediaPress, MangaListPress, SettingsPress, UpdatesPress},
     page::{
-        CancelListUpdate, CoverChange, IncrementMediaProgress, ListFilterTextChange,
-        ListGroupSelected, Login, Logout, MediaChange, RefreshLists, SettingChange,
+        AcceptUpdate, AddChosen, AskRepeat, AskStatusChange, CancelListUpdate, ChooseMedia,
+        CoverChange, DiscardFailed, DismissError, EditUpdate, IncrementMediaProgress,
+        ListFilterTextChange, ListGroupSelected, Login, Logout, MatchChosen, MediaChange,
+        RefreshLists, RepeatChosen, RetryFailed, SendUpdateNow, SettingChange, StatusChangeChosen,
+        SubmitToken, TokenInputChange,
     },
 };
 
@@ -260,22 +565,41 @@ pub enum Message {
     CoverRetrieved,
     SearchMedia,
     SearchResults,
+    EpisodeResolved,
+    EntryAdded,
     MediaUpdateComplete,
+    RequestFailed,
 
     // Nav
     AnimeListPress,
     MangaListPress,
     CurrentMediaPress,
+    UpdatesPress,
     SettingsPress,
 
     // Page
+    AddChosen,
     CoverChange,
     MediaChange,
     RefreshLists,
     Logout,
     Login,
     CancelListUpdate,
+    AcceptUpdate,
+    EditUpdate,
+    SendUpdateNow,
+    RetryFailed,
+    DiscardFailed,
+    DismissError,
+    ChooseMedia,
+    MatchChosen,
+    AskRepeat,
+    RepeatChosen,
+    AskStatusChange,
+    StatusChangeChosen,
     SettingChange,
+    TokenInputChange,
+    SubmitToken,
     ListGroupSelected,
     IncrementMediaProgress,
     ListFilterTextChange,
@@ -321,6 +645,15 @@ impl Event for DetectMediaResult {
                         app.recognized = Some(detected_media.clone());
                         return Some(forward_message(SearchMedia(detected_media, false).into()));
                     } else {
+                        // Same media as before, keep track of how long it's been watched
+                        if let Some(media_list) = &app.media {
+                            app.updates
+                                .observe(media_list.media_id, detected_media.playback);
+                            app.page
+                                .current_media
+                                .set_watch_progress(app.updates.progress(media_list.media_id));
+                        }
+                        app.recognized = Some(detected_media);
                         return None;
                     }
                 }
@@ -363,6 +696,8 @@ impl Event for MediaFound {
 
         app.media = Some(media.clone());
         app.recognized = Some(detected_media.clone());
+        let detected_media_playback = detected_media.playback;
+        let detected_media_type = detected_media.media_type;
 
         let msg = MediaChange(Some(media.clone()), Some(detected_media), needs_update).into();
         let mut commands = vec![forward_message(msg)];
@@ -390,7 +725,17 @@ impl Event for MediaFound {
         }
 
         if needs_update {
-            app.updates.enqueue(media);
+            let policy = settings::get_settings()
+                .read()
+                .unwrap()
+                .watch_policy
+                .get(detected_media_type);
+            let media_id = media.media_id;
+            app.updates
+                .enqueue_detected(media, detected_media_playback, policy);
+            app.page
+                .current_media
+                .set_watch_progress(app.updates.progress(media_id));
         } else {
             debug!("update not needed for media id {}", media.media_id);
         }
@@ -403,14 +748,11 @@ pub struct MediaNotFound;
 
 impl Event for MediaNotFound {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
-        let media_id = app.media.as_ref()?.media_id;
+        // The queued update stays, the queue drops it if it wasn't watched enough
+        app.media.as_ref()?;
         app.recognized = None;
         app.media = None;
         app.media_cover = None;
-        let index = app.updates.find_index(media_id);
-        if let Some(index) = index {
-            app.updates.remove(index);
-        }
         Some(forward_message(MediaChange(None, None, false).into()))
     }
 }
@@ -422,123 +764,164 @@ impl Event for SearchMedia {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
         let SearchMedia(recognized, oneshot) = self;
         app.recognized = Some(recognized.clone());
-        let token = {
+        app.page.current_media.set_match_choices(Vec::new());
+        app.page.current_media.set_add_prompt(None);
+        let key = recognition::normalize_title(&recognized.title).alias_key();
+        let (token, alias) = {
             let settings = settings::get_settings().read().unwrap();
-            settings.anilist.token().clone()
-        }?;
-        Some(App::query_search(token, recognized, oneshot))
+            let alias = settings.aliases.get(&key, recognized.media_type);
+            (settings.anilist.token().clone(), alias)
+        };
+        if let Some(media_id) = alias {
+            debug!("{} is aliased to media {}", recognized.title, media_id);
+            return app.media_matched(media_id, recognized);
+        }
+        Some(App::query_search(token?, recognized, oneshot, false))
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct SearchResults(Vec<Option<anilist::Media>>, bool);
+pub struct SearchResults(Vec<Option<anilist::Media>>, bool, bool);
 
 impl Event for SearchResults {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
-        let SearchResults(results, oneshot) = self;
+        let SearchResults(results, oneshot, choose) = self;
         let results: Vec<Option<&anilist::Media>> = results
             .iter()
             .filter_map(|m| m.as_ref())
             .map(|m| Some(m))
             .collect();
-        let mut recognized = app.recognized.clone()?;
-        let mut id =
-            anilist::MediaListCollection::best_id_for_search(&results, &recognized.title, oneshot)?;
-        let progress = {
-            let list = match recognized.media_type {
-                anilist::MediaType::Anime => app.page.anime.get_list(),
-                anilist::MediaType::Manga => app.page.manga.get_list(),
-            };
-            let progress = match recognized.media_type {
-                anilist::MediaType::Anime => {
-                    match list {
-                        Some(list) => match recognized.progress {
-                            Some(new_progress) => {
-                                let mut offset_progress =
-                                    list.compute_progress_offset_by_id(id, new_progress as i32);
-
-                                if let Some(offset) = offset_progress {
-                                    if offset < 0 {
-                                        // try to find offset for immediate sequel
-                                        let sequel_offset = list
-                                            .compute_progress_offset_for_sequel(
-                                                id,
-                                                new_progress as i32,
-                                            );
-                                        match sequel_offset {
-                                            Some((offset, sequel_id)) => {
-                                                offset_progress = Some(offset);
-                                                id = sequel_id
-                                            }
-                                            None => {}
-                                        }
-                                    }
-                                }
-
-                                offset_progress
-                            }
-                            None => None,
-                        },
-                        None => None,
-                    }
+        let recognized = app.recognized.clone()?;
+        if choose {
+            let matcher = anilist::Matcher::from_settings();
+            let in_list = anilist::MediaListCollection::search_results_in_list(&results, oneshot);
+            let current_id = app.media.as_ref().map(|media| media.media_id);
+            let mut candidates: Vec<anilist::MatchCandidate> = matcher
+                .rank_above(&in_list, &recognized.title, 0.0)
+                .into_iter()
+                .filter(|candidate| Some(candidate.media_id) != current_id)
+                .collect();
+            candidates.truncate(matcher.settings().max_choices);
+            if candidates.is_empty() {
+                debug!("no other media in the lists for {}", recognized.title);
+            }
+            app.page.current_media.set_match_choices(candidates);
+            return None;
+        }
+        let ranked =
+            anilist::MediaListCollection::rank_search_results(&results, &recognized.title, oneshot);
+        match anilist::Matcher::from_settings().decide(ranked, recognized.progress) {
+            anilist::MatchDecision::Accept(candidate) => {
+                debug!(
+                    "matched {} to {} with confidence {}",
+                    recognized.title, candidate.title, candidate.confidence
+                );
+                app.media_matched(candidate.media_id, recognized)
+            }
+            anilist::MatchDecision::Ambiguous(candidates) => {
+                debug!(
+                    "{} candidates for {}, asking which one it is",
+                    candidates.len(),
+                    recognized.title
+                );
+                app.page.current_media.set_match_choices(candidates);
+                None
+            }
+            anilist::MatchDecision::NoMatch => {
+                debug!(
+                    "no search results in the lists matched {}",
+                    recognized.title
+                );
+                app.media = None;
+                let (auto_add, token) = {
+                    let settings = settings::get_settings().read().unwrap();
+                    (settings.auto_add, settings.anilist.token().clone())
+                };
+                let mut commands = vec![forward_message(
+                    MediaChange(None, Some(recognized.clone()), false).into(),
+                )];
+                if auto_add.mode == anilist::AutoAddMode::Off {
+                    return Some(Command::batch(commands));
                 }
-                _ => None,
-            };
-
-            progress
-        };
-
-        let list = match recognized.media_type {
-            anilist::MediaType::Anime => app.page.anime.get_list_mut(),
-            anilist::MediaType::Manga => app.page.manga.get_list_mut(),
-        }?;
-
-        let entry = list.find_entry_by_id_mut(id);
-        match entry {
-            Some(media) => {
-                // Check if the detected progress is larger than the media's maximum number of episodes/chapters
-                // This is most likely an nth season where the count rolled over
-                if let Some(progress) = progress {
-                    if let Some(recognized_progress) = recognized.progress {
-                        if progress > 0 && progress < recognized_progress as i32 {
+                let best = anilist::MediaListCollection::rank_search_results_not_in_list(
+                    &results,
+                    &recognized.title,
+                    oneshot,
+                )
+                .into_iter()
+                .next();
+                if let Some(best) = best {
+                    match auto_add.mode_for(best.confidence) {
+                        anilist::AutoAddMode::Automatic => {
                             debug!(
-                                "offset progress of media {} to {} instead of {}",
-                                media.media_id, progress, recognized_progress
-                            );
-                            recognized.progress = Some(progress as f64);
-                        } else {
-                            recognized.progress = None;
-                            warn!(
-                                "detected progress offset error for media {}, {} became {}",
-                                media.media_id, recognized_progress, progress
+                                "adding {} to the list for {} with confidence {}",
+                                best.title, recognized.title, best.confidence
                             );
+                            commands.push(App::add_to_list(token?, best.media_id, recognized));
                         }
-                    } else {
-                        warn!("no recognized progress from {:#?}", recognized);
+                        anilist::AutoAddMode::Ask => {
+                            app.page.current_media.set_add_prompt(Some(best));
+                        }
+                        anilist::AutoAddMode::Off => {}
                     }
-                } else {
-                    debug!("progress offset was None for media id {}", media.media_id);
                 }
-
-                // Clone the media so we only mutate the entry in the user's list
-                // when the request is going to be sent, since the update can be cancelled
-                let mut media_copy = media.clone();
-                let needs_update =
-                    media_copy.update_progress(recognized.progress, recognized.progress_volumes);
-                Some(forward_message(
-                    MediaFound(media_copy, recognized, needs_update).into(),
-                ))
+                Some(Command::batch(commands))
             }
-            None => {
-                debug!("could not find media in list");
-                Some(forward_message(MediaNotFound.into()))
+        }
+    }
+}
+
+/// The franchise entry and episode an episode past the end of the matched media is
+#[derive(Debug, Clone)]
+pub struct EpisodeResolved(i32, recognition::Media, Option<(i32, f64)>);
+
+impl Event for EpisodeResolved {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let EpisodeResolved(id, mut recognized, resolved) = self;
+        if app.recognized.as_ref() != Some(&recognized) {
+            debug!(
+                "recognized media changed while resolving episode of media {}",
+                id
+            );
+            return None;
+        }
+        match resolved {
+            Some((resolved_id, episode)) => {
+                debug!(
+                    "resolved media {} episode {:?} to media {} episode {}",
+                    id, recognized.progress, resolved_id, episode
+                );
+                recognized.set_progress(Some(episode));
+                app.queue_matched(resolved_id, recognized, None)
             }
+            None => app.offset_matched(id, recognized),
         }
     }
 }
 
+/// A media was added to the user's list for the recognized media
 #[derive(Debug, Clone)]
-pub struct Authorized(String);
+pub struct EntryAdded(anilist::MediaList, recognition::Media);
+
+impl Event for EntryAdded {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let EntryAdded(entry, recognized) = self;
+        let media_id = entry.media_id;
+        info!("added media {} to the list", media_id);
+        match recognized.media_type {
+            anilist::MediaType::Anime => app.page.anime.insert_entry(entry),
+            anilist::MediaType::Manga => app.page.manga.insert_entry(entry),
+        }
+        if app.recognized.as_ref() != Some(&recognized) {
+            debug!("recognized media changed while adding media {}", media_id);
+            return None;
+        }
+        app.media_matched(media_id, recognized)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Authorized(pub String);
 
 impl Event for Authorized {
     fn handle(self, _app: &mut App) -> Option<Command<Message>> {
@@ -605,12 +988,25 @@ impl Event for AvatarRetrieved {
 pub struct ListRetrieved {
     anime_list: Option<anilist::MediaListCollection>,
     manga_list: Option<anilist::MediaListCollection>,
+    error: Option<anilist::AniListError>,
 }
 
 impl Event for ListRetrieved {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
         app.page.anime.set_list(self.anime_list);
         app.page.manga.set_list(self.manga_list);
+        let page = &app.page;
+        app.updates.check_restored(|update| {
+            let list = match update.media.as_ref().and_then(|media| media.media_type) {
+                Some(anilist::MediaType::Anime) => page.anime.get_list(),
+                Some(anilist::MediaType::Manga) => page.manga.get_list(),
+                None => None,
+            };
+            match list.and_then(|list| list.find_entry_by_id(update.media_id)) {
+                Some(entry) => update.changes_from(entry).is_empty(),
+                None => false,
+            }
+        });
         info!(
             "anime list was retrieved? {}",
             app.page.anime.get_list().is_some()
@@ -619,7 +1015,10 @@ impl Event for ListRetrieved {
             "manga list was retrieved? {}",
             app.page.manga.get_list().is_some()
         );
-        None
+        match &self.error {
+            Some(err) => app.request_failed("Could not get your lists", err),
+            None => None,
+        }
     }
 }
 
@@ -636,15 +1035,44 @@ impl Event for CoverRetrieved {
 }
 
 #[derive(Debug, Clone)]
-pub struct MediaUpdateComplete;
+pub struct MediaUpdateComplete(Result<(), anilist::AniListError>);
 
 impl Event for MediaUpdateComplete {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let MediaUpdateComplete(result) = self;
         app.updates.set_waiting(false);
+        let outcome = anilist::SendOutcome::from_result(&result);
+        // Failed updates stay on the updates page, only a rejected token needs telling
+        if let Err(err) = &result {
+            if err.needs_login() {
+                app.updates.finish(outcome);
+                return app.request_failed("Could not update your list", err);
+            }
+        }
+        // The cached list only changes once AniList has the update
+        let sent = app.updates.finish(outcome)?;
+        let list = match sent.media.as_ref()?.media_type? {
+            anilist::MediaType::Anime => app.page.anime.get_list_mut(),
+            anilist::MediaType::Manga => app.page.manga.get_list_mut(),
+        }?;
+        if let Some(entry) = list.find_entry_by_id_mut(sent.media_id) {
+            *entry = sent;
+        }
         None
     }
 }
 
+/// A request to AniList failed, what was being done and why it failed
+#[derive(Debug, Clone)]
+pub struct RequestFailed(&'static str, anilist::AniListError);
+
+impl Event for RequestFailed {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let RequestFailed(what, err) = self;
+        app.request_failed(what, &err)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NoMessage;
 
@@ -653,3 +1081,36 @@ impl Event for NoMessage {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use recognition::{FixtureSource, MediaSources};
+    use settings::RecognitionData;
+
+    #[tokio::test]
+    async fn detected_media_is_searched_once() {
+        let parser = MediaParser::new(&RecognitionData::load().unwrap().rules).unwrap();
+        let mut sources = MediaSources::default();
+        sources.push(
+            Box::new(FixtureSource::new(vec![
+                "Stream Episode 5 of Made in Abyss on HIDIVE".to_string(),
+            ])),
+            0,
+        );
+        let detected = parser.detect_from(&sources).await;
+        assert!(detected.is_some());
+
+        let mut app = App::default();
+        assert!(DetectMediaResult(detected.clone())
+            .handle(&mut app)
+            .is_some());
+        assert_eq!(app.recognized, detected);
+
+        // The same media being detected again shouldn't trigger another search
+        assert!(DetectMediaResult(detected.clone())
+            .handle(&mut app)
+            .is_none());
+        assert_eq!(app.recognized, detected);
+    }
+}
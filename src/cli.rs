@@ -0,0 +1,335 @@
+use crate::{
+    anilist::{MediaType, TransportMode},
+    recognition::MediaParser,
+    settings::{EndpointOverrides, ExpectedMatch, RecognitionData},
+};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+const RECOGNIZE_USAGE: &str = "\
+usage: fubuki recognize [--json] [--file PATH] [TITLE...]
+       fubuki recognize --verify
+
+Checks window titles against the bundled and custom recognition rules.
+Titles are read from the arguments, the file given with --file, or stdin
+when neither is given (one title per line).
+
+options:
+    --json         print one JSON object per title
+    --file PATH    read titles from PATH
+    --verify       run every example in the rule files, failing on mismatches
+    -h, --help     print this message";
+
+const GLOBAL_USAGE: &str = "\
+usage: fubuki [OPTIONS] [COMMAND]
+
+Options go before the command. The endpoint options are used over the
+settings file and the FUBUKI_GRAPHQL_URL, FUBUKI_AUTHORIZE_URL,
+FUBUKI_TOKEN_URL, FUBUKI_CLIENT_ID and FUBUKI_REDIRECT_URI environment
+variables, --record and --replay over FUBUKI_RECORD and FUBUKI_REPLAY.
+
+options:
+    --graphql-url URL      the AniList GraphQL API
+    --authorize-url URL    the page the login starts on
+    --token-url URL        where the login code is exchanged for a token
+    --client-id ID         the OAuth client id
+    --redirect-uri URI     where the login redirects to
+    --record DIR           save every AniList response to cassettes in DIR
+    --replay DIR           answer AniList requests from the cassettes in DIR
+                           without the network, for tests and demos";
+
+/// The options that apply to the GUI and every command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlobalArgs {
+    pub endpoints: EndpointOverrides,
+    pub transport: Option<TransportMode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    Recognize(RecognizeArgs),
+    Help(&'static str),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecognizeArgs {
+    pub titles: Vec<String>,
+    pub file: Option<String>,
+    pub json: bool,
+    pub verify: bool,
+}
+
+/// Takes the global options from the front of the arguments, returning the ones left
+pub fn parse_global_args<I: IntoIterator<Item = String>>(
+    args: I,
+) -> Result<(GlobalArgs, Vec<String>)> {
+    let mut global = GlobalArgs::default();
+    let mut args = args.into_iter().peekable();
+    while let Some(flag) = args.peek().cloned() {
+        let known = [
+            "--graphql-url",
+            "--authorize-url",
+            "--token-url",
+            "--client-id",
+            "--redirect-uri",
+            "--record",
+            "--replay",
+        ];
+        if !known.contains(&flag.as_str()) {
+            break;
+        }
+        args.next();
+        let value = match args.next() {
+            Some(value) => value,
+            None => return Err(anyhow!("{} needs a value\n\n{}", flag, GLOBAL_USAGE)),
+        };
+        let endpoints = &mut global.endpoints;
+        match flag.as_str() {
+            "--graphql-url" => endpoints.graphql = Some(value),
+            "--authorize-url" => endpoints.authorize = Some(value),
+            "--token-url" => endpoints.token = Some(value),
+            "--client-id" => endpoints.client_id = Some(value),
+            "--redirect-uri" => endpoints.redirect_uri = Some(value),
+            "--record" => global.transport = Some(TransportMode::Record(value.into())),
+            _ => global.transport = Some(TransportMode::Replay(value.into())),
+        }
+    }
+    Ok((global, args.collect()))
+}
+
+/// Parses the arguments after the program name, returns None if the GUI should start
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<CliCommand>> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("recognize") => {}
+        Some(arg) => return Err(anyhow!("unknown command {}\n\n{}", arg, GLOBAL_USAGE)),
+        None => return Ok(None),
+    }
+
+    let mut recognize = RecognizeArgs::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => recognize.json = true,
+            "--verify" => recognize.verify = true,
+            "--file" => match args.next() {
+                Some(path) => recognize.file = Some(path),
+                None => return Err(anyhow!("--file needs a path\n\n{}", RECOGNIZE_USAGE)),
+            },
+            "-h" | "--help" => return Ok(Some(CliCommand::Help(RECOGNIZE_USAGE))),
+            "--" => {
+                recognize.titles.extend(args);
+                break;
+            }
+            flag if flag.starts_with("--") => {
+                return Err(anyhow!("unknown option {}\n\n{}", flag, RECOGNIZE_USAGE))
+            }
+            _ => recognize.titles.push(arg),
+        }
+    }
+    Ok(Some(CliCommand::Recognize(recognize)))
+}
+
+/// Runs the command, returning the exit code
+pub fn run(command: CliCommand) -> Result<i32> {
+    match command {
+        CliCommand::Help(usage) => {
+            println!("{}", usage);
+            Ok(0)
+        }
+        CliCommand::Recognize(args) => recognize(args),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Recognition {
+    pub input: String,
+    pub rule: Option<String>,
+    pub media_type: Option<MediaType>,
+    #[serde(flatten)]
+    pub captures: Option<ExpectedMatch>,
+    pub errors: Vec<String>,
+}
+
+impl Recognition {
+    pub fn new(parser: &MediaParser, input: String) -> Self {
+        let (media, errors) = parser.parse_title_with_errors(&input);
+        Recognition {
+            input,
+            rule: media.as_ref().and_then(|media| media.rule.clone()),
+            media_type: media.as_ref().map(|media| media.media_type),
+            captures: media.map(|media| media.expected_match()),
+            errors,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![self.input.clone()];
+        match (&self.rule, &self.captures) {
+            (Some(rule), Some(captures)) => {
+                lines.push(format!("    rule: {}", rule));
+                if let Some(media_type) = self.media_type {
+                    lines.push(format!("    type: {:?}", media_type));
+                }
+                lines.push(format!("    title: {}", captures.title));
+                let numbers = [
+                    ("episode", captures.episode),
+                    ("episode end", captures.episode_end),
+                    ("chapter", captures.chapter),
+                    ("chapter end", captures.chapter_end),
+                    ("volume", captures.volume),
+                    ("version", captures.version.map(f64::from)),
+                ];
+                for (name, number) in numbers.iter() {
+                    if let Some(n) = number {
+                        lines.push(format!("    {}: {}", name, n));
+                    }
+                }
+            }
+            _ => lines.push("    no match".to_string()),
+        }
+        for err in self.errors.iter() {
+            lines.push(format!("    error: {}", err));
+        }
+        lines.join("\n")
+    }
+}
+
+fn read_titles<R: BufRead>(rdr: R) -> Result<Vec<String>> {
+    let mut titles = Vec::new();
+    for line in rdr.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            titles.push(line);
+        }
+    }
+    Ok(titles)
+}
+
+fn recognize(args: RecognizeArgs) -> Result<i32> {
+    let data = RecognitionData::load_with_custom()?;
+    if args.verify {
+        return verify(&data);
+    }
+
+    let (parser, errors) = MediaParser::with_errors(&data.rules);
+    for err in errors.iter() {
+        eprintln!("skipping {}", err);
+    }
+    let mut titles = args.titles;
+    if let Some(path) = &args.file {
+        let file = File::open(path).map_err(|err| anyhow!("could not open {}: {}", path, err))?;
+        titles.extend(read_titles(BufReader::new(file))?);
+    } else if titles.is_empty() {
+        let stdin = io::stdin();
+        titles = read_titles(stdin.lock())?;
+    }
+
+    for title in titles {
+        let recognition = Recognition::new(&parser, title);
+        if args.json {
+            println!("{}", serde_json::to_string(&recognition)?);
+        } else {
+            println!("{}", recognition.to_text());
+        }
+    }
+    Ok(0)
+}
+
+fn verify(data: &RecognitionData) -> Result<i32> {
+    let failures = MediaParser::verify_examples(&data.rules)?;
+    let examples: usize = data
+        .rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .map(|rule| rule.examples.len())
+        .sum();
+    for failure in failures.iter() {
+        println!("FAIL {}", failure);
+    }
+    println!(
+        "{} of {} examples passed",
+        examples - failures.len(),
+        examples
+    );
+    Ok(if failures.is_empty() { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_recognize_args() {
+        assert_eq!(parse_args(args(&[])).unwrap(), None);
+        assert!(parse_args(args(&["unknown"])).is_err());
+        assert!(parse_args(args(&["recognize", "--file"])).is_err());
+        assert_eq!(
+            parse_args(args(&["recognize", "--json", "a", "--", "--b"])).unwrap(),
+            Some(CliCommand::Recognize(RecognizeArgs {
+                titles: args(&["a", "--b"]),
+                file: None,
+                json: true,
+                verify: false,
+            }))
+        );
+    }
+
+    #[test]
+    fn parses_global_args() {
+        let (global, rest) = parse_global_args(args(&[
+            "--graphql-url",
+            "http://localhost:4000",
+            "--replay",
+            "cassettes",
+            "recognize",
+            "--client-id",
+        ]))
+        .unwrap();
+        assert_eq!(
+            global.endpoints.graphql.as_deref(),
+            Some("http://localhost:4000")
+        );
+        assert_eq!(global.endpoints.client_id, None);
+        assert_eq!(
+            global.transport,
+            Some(TransportMode::Replay("cassettes".into()))
+        );
+        assert_eq!(rest, args(&["recognize", "--client-id"]));
+        assert!(parse_global_args(args(&["--token-url"])).is_err());
+    }
+
+    #[test]
+    fn recognizes_titles_as_json() {
+        let parser = MediaParser::new(&RecognitionData::load().unwrap().rules).unwrap();
+        let title = "[#1000] One Piece | MANGA Plus by SHUEISHA".to_string();
+        let json = serde_json::to_value(Recognition::new(&parser, title)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "input": "[#1000] One Piece | MANGA Plus by SHUEISHA",
+                "rule": "manga-plus",
+                "media_type": "MANGA",
+                "title": "One Piece",
+                "episode": null,
+                "episode_end": null,
+                "chapter": 1000.0,
+                "chapter_end": null,
+                "volume": null,
+                "version": null,
+                "errors": [],
+            })
+        );
+
+        let unmatched = Recognition::new(&parser, "Terminal".to_string());
+        assert_eq!(unmatched.rule, None);
+        assert_eq!(unmatched.to_text(), "Terminal\n    no match");
+    }
+}
@@ -3,6 +3,7 @@ extern crate winapi;
 
 pub mod anilist;
 pub mod app;
+pub mod cli;
 pub mod recognition;
 pub mod resources;
 pub mod settings;
@@ -16,13 +17,14 @@ use settings::file_path;
 //#![windows_subsystem = "windows"] // Tells windows compiler not to show console window
 
 use log::{warn, LevelFilter};
-use log4rs::append::console::ConsoleAppender;
+use log4rs::append::console::{ConsoleAppender, Target};
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Logger, Root};
 use log4rs::encode::json::JsonEncoder;
 
-fn initialize_logger() -> Result<()> {
-    let stdout = ConsoleAppender::builder().build();
+/// Command line output goes to stdout, so logs go to stderr instead there
+fn initialize_logger(target: Target) -> Result<()> {
+    let stdout = ConsoleAppender::builder().target(target).build();
     let path = file_path("fubuki.log")?;
     {
         // Truncate the log file if it exists
@@ -59,7 +61,24 @@ fn initialize_logger() -> Result<()> {
 }
 
 fn main() -> Result<()> {
-    initialize_logger()?;
+    let (global, args) = cli::parse_global_args(std::env::args().skip(1))?;
+    settings::set_endpoint_overrides(global.endpoints);
+    if let Some(command) = cli::parse_args(args)? {
+        initialize_logger(Target::Stderr)?;
+        let code = cli::run(command)?;
+        std::process::exit(code);
+    }
+
+    initialize_logger(Target::Stdout)?;
+    if let Some(mode) = global.transport.or_else(anilist::TransportMode::from_env) {
+        let replay = matches!(mode, anilist::TransportMode::Replay(_));
+        anilist::set_transport(mode.transport()?);
+        // Replayed requests don't need a login, so demos start logged in
+        if replay {
+            let mut settings = settings::get_settings().write().unwrap();
+            settings.anilist.use_session_token("replay");
+        }
+    }
     let mut settings = Settings::default();
     if let Err(err) = app::set_icon(&mut settings) {
         warn!("could not load application icon: {}", err);
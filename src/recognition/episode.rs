@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How an episode between two others, like a "12.5" recap, is counted in the list
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FractionalEpisodes {
+    /// Leave the list alone
+    Skip,
+    /// Count it as the episode before, 12.5 as 12
+    RoundDown,
+    /// Count it as the special of the media in the user's list, if there is one
+    Special,
+}
+
+impl Default for FractionalEpisodes {
+    fn default() -> Self {
+        FractionalEpisodes::Skip
+    }
+}
+
+/// An episode or chapter number as it appears in a title
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Episode {
+    pub number: f64,
+    /// The last episode of a batch like "01-03"
+    pub end: Option<f64>,
+    /// The release version, 2 for "05v2"
+    pub version: Option<i32>,
+}
+
+impl Episode {
+    pub fn new(number: f64) -> Self {
+        Episode {
+            number,
+            end: None,
+            version: None,
+        }
+    }
+
+    /// The last episode the title covers
+    pub fn last(&self) -> f64 {
+        match self.end {
+            Some(end) if end > self.number => end,
+            _ => self.number,
+        }
+    }
+
+    pub fn is_range(&self) -> bool {
+        self.last() > self.number
+    }
+
+    pub fn is_fractional(&self) -> bool {
+        self.last().fract() != 0.0
+    }
+
+    /// Renumbers the episode, keeping how many episodes a batch covers
+    pub fn renumber(&self, number: f64) -> Self {
+        Episode {
+            number,
+            end: self.end.map(|end| end - self.number + number),
+            version: self.version,
+        }
+    }
+
+    /// Moves the whole part of the number to `whole`, for offsets worked out from whole episodes.
+    /// The fraction is kept so a "25.5" recap offset to 13 is still a recap, "13.5".
+    pub fn offset_to(&self, whole: i32) -> Self {
+        self.renumber(self.number - self.number.trunc() + whole as f64)
+    }
+
+    /// The progress the list should have after the episode, None if it shouldn't be updated.
+    /// Batches count up to their last episode.
+    pub fn list_progress(&self, fractional: FractionalEpisodes) -> Option<i32> {
+        let last = self.last();
+        if !self.is_fractional() {
+            return Some(last as i32);
+        }
+        match fractional {
+            FractionalEpisodes::RoundDown => Some(last.floor() as i32),
+            // Specials are moved to their own entry before this, so there was none
+            FractionalEpisodes::Skip | FractionalEpisodes::Special => None,
+        }
+    }
+}
+
+impl fmt::Display for Episode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.number)?;
+        if self.is_range() {
+            write!(f, "-{}", self.last())?;
+        }
+        if let Some(version) = self.version {
+            write!(f, "v{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_episodes_to_list_progress() {
+        let batch = Episode {
+            number: 1.0,
+            end: Some(3.0),
+            version: None,
+        };
+        assert_eq!(batch.list_progress(FractionalEpisodes::Skip), Some(3));
+        assert_eq!(batch.renumber(13.0).last(), 15.0);
+        assert_eq!(batch.to_string(), "1-3");
+
+        let recap = Episode::new(12.5);
+        assert_eq!(recap.list_progress(FractionalEpisodes::Skip), None);
+        assert_eq!(recap.list_progress(FractionalEpisodes::RoundDown), Some(12));
+        assert_eq!(recap.list_progress(FractionalEpisodes::Special), None);
+
+        let continued = Episode::new(25.5).offset_to(13);
+        assert_eq!(continued.number, 13.5);
+        assert_eq!(continued.list_progress(FractionalEpisodes::Skip), None);
+        assert_eq!(
+            continued.list_progress(FractionalEpisodes::RoundDown),
+            Some(13)
+        );
+        assert_eq!(batch.renumber(25.0).offset_to(13).last(), 15.0);
+
+        let version = Episode {
+            version: Some(2),
+            ..Episode::new(5.0)
+        };
+        assert_eq!(version.list_progress(FractionalEpisodes::Skip), Some(5));
+        assert_eq!(version.to_string(), "5v2");
+    }
+}
@@ -0,0 +1,907 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+const VIDEO_EXTENSIONS: [&str; 12] = [
+    "mkv", "mp4", "avi", "m4v", "mov", "wmv", "flv", "webm", "ogm", "ts", "m2ts", "rmvb",
+];
+
+/// Words that only describe the release, the title never continues past one of these
+const KEYWORDS: [&str; 32] = [
+    "bd",
+    "bdrip",
+    "bluray",
+    "blu-ray",
+    "dvd",
+    "dvdrip",
+    "web",
+    "web-dl",
+    "webrip",
+    "hdtv",
+    "tv",
+    "x264",
+    "x265",
+    "h264",
+    "h265",
+    "h.264",
+    "h.265",
+    "hevc",
+    "avc",
+    "aac",
+    "flac",
+    "ac3",
+    "opus",
+    "10bit",
+    "8bit",
+    "hi10",
+    "hi10p",
+    "dual-audio",
+    "multi-sub",
+    "raw",
+    "end",
+    "batch",
+];
+
+static RESOLUTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:\d{3,4}[pP]|\d{3,4}[xX]\d{3,4}|4[kK])$").unwrap());
+static CHECKSUM: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[0-9A-Fa-f]{8}$").unwrap());
+static YEAR: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:19|20)\d{2}$").unwrap());
+static NUMBER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d{1,4}(?:\.\d)?)(?:v(\d))?$").unwrap());
+
+// Episode patterns, tried in order. Each has `episode` and optionally `season`, `end` and `version` groups.
+static SEASON_EPISODE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:^|\s)S(?P<season>\d{1,2})\s?E(?P<episode>\d{1,4})(?:-E?(?P<end>\d{1,4}))?(?:v(?P<version>\d))?(?:\s|$)").unwrap()
+});
+static SEASON_X_EPISODE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:^|\s)(?P<season>\d{1,2})x(?P<episode>\d{2,3})(?:v(?P<version>\d))?(?:\s|$)")
+        .unwrap()
+});
+static EPISODE_PREFIX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)(?:^|\s)(?:episode\s?|ep\.?\s?|e|#)(?P<episode>\d{1,4}(?:\.\d)?)(?:\s?[-~]\s?(?P<end>\d{1,4}))?(?:v(?P<version>\d))?(?:\s|$)").unwrap()
+});
+static DASH_EPISODE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:^|\s)-\s+(?P<episode>\d{1,4}(?:\.\d)?)(?:\s?[-~]\s?(?P<end>\d{1,4}(?:\.\d)?))?(?:v(?P<version>\d))?(?:\s|$)").unwrap()
+});
+static TRAILING_EPISODE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"\s(?P<episode>\d{1,4}(?:\.\d)?)(?:\s?[-~]\s?(?P<end>\d{1,4}))?(?:v(?P<version>\d))?$",
+    )
+    .unwrap()
+});
+
+static TITLE_SEASON: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\s(?:S(?P<short>\d{1,2})|Season\s(?P<long>\d{1,2})|(?P<ordinal>\d{1,2})(?:st|nd|rd|th)\sSeason)$").unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParsedFilename {
+    pub title: String,
+    pub season: Option<i32>,
+    pub episode: Option<f64>,
+    pub episode_end: Option<f64>,
+    pub version: Option<i32>,
+    pub release_group: Option<String>,
+    pub resolution: Option<String>,
+    pub checksum: Option<String>,
+    pub extension: Option<String>,
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    text: &'a str,
+    enclosed: bool,
+}
+
+pub fn is_video_file(name: &str) -> bool {
+    split_extension(file_name(name)).1.is_some()
+}
+
+/// Parses a release file name like `[Group] Show Name S2 - 05v2 (1080p) [ABCD1234].mkv`.
+/// Directories in front of the file name are ignored.
+pub fn parse_filename(input: &str) -> Option<ParsedFilename> {
+    let (stem, extension) = split_extension(file_name(input));
+    let mut parsed = ParsedFilename {
+        extension: extension.map(String::from),
+        ..ParsedFilename::default()
+    };
+
+    let tokens = tokenize(stem);
+    let mut free_text = String::new();
+    let mut enclosed = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if !token.enclosed {
+            free_text.push(' ');
+            free_text.push_str(&replace_delimiters(token.text));
+        } else if !parse_enclosed(token.text, &mut parsed) {
+            if i == 0 && parsed.release_group.is_none() {
+                parsed.release_group = Some(token.text.trim().to_string());
+            } else {
+                enclosed.push(token.text.trim());
+            }
+        }
+    }
+
+    if free_text.trim().is_empty() {
+        // Everything is in brackets, e.g. `[Group][Title][05][720p]`
+        free_text = enclosed.join(" ");
+    }
+    let free_text = collapse_whitespace(&free_text);
+    if parsed.resolution.is_none() {
+        parsed.resolution = free_text
+            .split(' ')
+            .find(|word| RESOLUTION.is_match(word))
+            .map(String::from);
+    }
+
+    let title = match find_episode(&free_text) {
+        Some((title, captures)) => {
+            parsed.episode = parse_number(&captures, "episode");
+            parsed.episode_end = parse_number(&captures, "end");
+            parsed.version = parse_number(&captures, "version").map(|v| v as i32);
+            parsed.season = parse_number(&captures, "season").map(|s| s as i32);
+            title
+        }
+        None => {
+            let title = truncate_at_keyword(&free_text);
+            match TRAILING_EPISODE.captures(&title) {
+                Some(captures) if !YEAR.is_match(&captures["episode"]) => {
+                    parsed.episode = parse_number(&captures, "episode");
+                    parsed.episode_end = parse_number(&captures, "end");
+                    parsed.version = parse_number(&captures, "version").map(|v| v as i32);
+                    title[..captures.get(0)?.start()].to_string()
+                }
+                _ => {
+                    // Fall back to an episode number in brackets, e.g. `Title [05]`
+                    let number = enclosed.iter().find_map(|text| NUMBER.captures(text));
+                    if let Some(captures) = number {
+                        parsed.episode = parse_number(&captures, "episode");
+                        parsed.version = parse_number(&captures, "version").map(|v| v as i32);
+                    }
+                    title
+                }
+            }
+        }
+    };
+
+    let mut title = clean_title(&title);
+    if let Some(captures) = TITLE_SEASON.captures(&title) {
+        let season = ["short", "long", "ordinal"]
+            .iter()
+            .find_map(|name| parse_number(&captures, name))
+            .map(|s| s as i32);
+        if parsed.season.is_none() {
+            parsed.season = season;
+        }
+        title = clean_title(&title[..captures.get(0)?.start()]);
+    }
+
+    if title.is_empty() {
+        None
+    } else {
+        parsed.title = title;
+        Some(parsed)
+    }
+}
+
+fn file_name(input: &str) -> &str {
+    input.rsplit(['/', '\\']).next().unwrap_or(input)
+}
+
+fn split_extension(name: &str) -> (&str, Option<&str>) {
+    if let Some(dot) = name.rfind('.') {
+        let extension = &name[dot + 1..];
+        let is_video = VIDEO_EXTENSIONS
+            .iter()
+            .any(|ext| ext.eq_ignore_ascii_case(extension));
+        if is_video {
+            return (&name[..dot], Some(extension));
+        }
+    }
+    (name, None)
+}
+
+fn tokenize(stem: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut close = None;
+    for (i, c) in stem.char_indices() {
+        match close {
+            None => {
+                let closing = match c {
+                    '[' => Some(']'),
+                    '(' => Some(')'),
+                    '{' => Some('}'),
+                    '【' => Some('】'),
+                    _ => None,
+                };
+                if closing.is_some() {
+                    if i > start {
+                        tokens.push(Token {
+                            text: &stem[start..i],
+                            enclosed: false,
+                        });
+                    }
+                    start = i + c.len_utf8();
+                    close = closing;
+                }
+            }
+            Some(closing) => {
+                if c == closing {
+                    tokens.push(Token {
+                        text: &stem[start..i],
+                        enclosed: true,
+                    });
+                    start = i + c.len_utf8();
+                    close = None;
+                }
+            }
+        }
+    }
+    if start < stem.len() {
+        tokens.push(Token {
+            text: &stem[start..],
+            enclosed: false,
+        });
+    }
+    tokens
+}
+
+/// Pulls the resolution and checksum out of bracketed text.
+/// Returns true if the text was release information rather than something like a title or group.
+fn parse_enclosed(text: &str, parsed: &mut ParsedFilename) -> bool {
+    let text = text.trim();
+    if CHECKSUM.is_match(text) && !text.chars().all(|c| c.is_ascii_digit()) {
+        parsed.checksum = Some(text.to_string());
+        return true;
+    }
+    if YEAR.is_match(text) {
+        return true;
+    }
+    let words: Vec<&str> = text.split([' ', '_', ',']).collect();
+    let mut is_info = false;
+    for word in words.iter() {
+        if RESOLUTION.is_match(word) {
+            if parsed.resolution.is_none() {
+                parsed.resolution = Some(word.to_string());
+            }
+            is_info = true;
+        } else if is_keyword(word) {
+            is_info = true;
+        }
+    }
+    is_info
+}
+
+/// Uses spaces as the delimiter, converting underscores and dots if the text has no spaces.
+/// Dots inside numbers like `12.5` are kept.
+fn replace_delimiters(text: &str) -> String {
+    let text = text.replace('_', " ");
+    if text.trim().contains(' ') || !text.contains('.') {
+        return text;
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let is_digit = |i: usize| matches!(chars.get(i), Some(c) if c.is_ascii_digit());
+    let mut replaced = String::with_capacity(text.len());
+    for (i, c) in chars.iter().enumerate() {
+        let is_decimal = i > 0
+            && is_digit(i - 1)
+            && is_digit(i + 1)
+            && !matches!(chars.get(i + 2), Some(c) if c.is_ascii_alphanumeric());
+        if *c == '.' && !is_decimal {
+            replaced.push(' ');
+        } else {
+            replaced.push(*c);
+        }
+    }
+    replaced
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+fn truncate_at_keyword(text: &str) -> String {
+    let words: Vec<&str> = text
+        .split(' ')
+        .take_while(|word| !is_keyword(word) && !RESOLUTION.is_match(word))
+        .collect();
+    words.join(" ")
+}
+
+/// Finds the episode with the most specific pattern, returning the text in front of it as the title
+fn find_episode(text: &str) -> Option<(String, Captures<'_>)> {
+    let patterns: [&Lazy<Regex>; 4] = [
+        &SEASON_EPISODE,
+        &SEASON_X_EPISODE,
+        &EPISODE_PREFIX,
+        &DASH_EPISODE,
+    ];
+    for pattern in patterns.iter() {
+        // The last match is used so numbers in titles like `86 - Eighty Six - 05` are skipped
+        if let Some(captures) = pattern.captures_iter(text).last() {
+            let start = captures.get(0)?.start();
+            return Some((text[..start].to_string(), captures));
+        }
+    }
+    None
+}
+
+fn parse_number(captures: &Captures, name: &str) -> Option<f64> {
+    captures.name(name)?.as_str().parse::<f64>().ok()
+}
+
+fn clean_title(title: &str) -> String {
+    let title = collapse_whitespace(title);
+    title
+        .trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '~' || c == '_')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Case {
+        input: &'static str,
+        title: &'static str,
+        season: Option<i32>,
+        episode: Option<f64>,
+        episode_end: Option<f64>,
+        version: Option<i32>,
+        group: Option<&'static str>,
+        resolution: Option<&'static str>,
+        checksum: Option<&'static str>,
+    }
+
+    const CASES: &[Case] = &[
+        Case {
+            input: "[Group] Show Name S2 - 05v2 (1080p) [ABCD1234].mkv",
+            title: "Show Name",
+            season: Some(2),
+            episode: Some(5.0),
+            episode_end: None,
+            version: Some(2),
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: Some("ABCD1234"),
+        },
+        Case {
+            input: "[HorribleSubs] Boku no Hero Academia - 88 [1080p].mkv",
+            title: "Boku no Hero Academia",
+            season: None,
+            episode: Some(88.0),
+            episode_end: None,
+            version: None,
+            group: Some("HorribleSubs"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[SubsPlease] Jujutsu Kaisen - 24 (1080p) [E9A1B6F2].mkv",
+            title: "Jujutsu Kaisen",
+            season: None,
+            episode: Some(24.0),
+            episode_end: None,
+            version: None,
+            group: Some("SubsPlease"),
+            resolution: Some("1080p"),
+            checksum: Some("E9A1B6F2"),
+        },
+        Case {
+            input: "[Erai-raws] Shingeki no Kyojin - The Final Season - 01 [1080p][Multiple Subtitle].mkv",
+            title: "Shingeki no Kyojin - The Final Season",
+            season: None,
+            episode: Some(1.0),
+            episode_end: None,
+            version: None,
+            group: Some("Erai-raws"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "Kimetsu.no.Yaiba.S01E19.1080p.WEB-DL.x264.mkv",
+            title: "Kimetsu no Yaiba",
+            season: Some(1),
+            episode: Some(19.0),
+            episode_end: None,
+            version: None,
+            group: None,
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Judas] Made in Abyss - S01E03.mkv",
+            title: "Made in Abyss",
+            season: Some(1),
+            episode: Some(3.0),
+            episode_end: None,
+            version: None,
+            group: Some("Judas"),
+            resolution: None,
+            checksum: None,
+        },
+        Case {
+            input: "[Coalgirls]_Toradora!_01_(1280x720_Blu-Ray_FLAC)_[2B3D5C8E].mkv",
+            title: "Toradora!",
+            season: None,
+            episode: Some(1.0),
+            episode_end: None,
+            version: None,
+            group: Some("Coalgirls"),
+            resolution: Some("1280x720"),
+            checksum: Some("2B3D5C8E"),
+        },
+        Case {
+            input: "[Group] Show Name - 01-03 [720p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(1.0),
+            episode_end: Some(3.0),
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 01~12 [BD 1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(1.0),
+            episode_end: Some(12.0),
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 12.5 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(12.5),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name 2nd Season - 05 [720p].mkv",
+            title: "Show Name",
+            season: Some(2),
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name Season 3 - 10 [1080p].mkv",
+            title: "Show Name",
+            season: Some(3),
+            episode: Some(10.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "Show Name - 05.mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: None,
+            resolution: None,
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Movie Name (2019) [1080p].mkv",
+            title: "Movie Name",
+            season: None,
+            episode: None,
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05 (BD 1080p HEVC FLAC) [12AB34CD].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: Some("12AB34CD"),
+        },
+        Case {
+            input: "[Group] Show Name - Episode 05 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name Ep05 [720p].mp4",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 12 END [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(12.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05 (1920x1080 x264 AAC).mp4",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1920x1080"),
+            checksum: None,
+        },
+        Case {
+            input: "Show_Name_-_05_[720p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: None,
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name #05 [720p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] 86 - Eighty Six - 05 [1080p].mkv",
+            title: "86 - Eighty Six",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Steins;Gate 0 - 05 [1080p].mkv",
+            title: "Steins;Gate 0",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Mob Psycho 100 II - 03 [1080p].mkv",
+            title: "Mob Psycho 100 II",
+            season: None,
+            episode: Some(3.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05v2.mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: Some(2),
+            group: Some("Group"),
+            resolution: None,
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name S02E05 [1080p].mkv",
+            title: "Show Name",
+            season: Some(2),
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - S2E05 [1080p].mkv",
+            title: "Show Name",
+            season: Some(2),
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name (2021) - 05 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05 [Web][1080p][AAC].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] One Piece - 1000 [1080p].mkv",
+            title: "One Piece",
+            season: None,
+            episode: Some(1000.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "Show Name - 05 - The Episode Title [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: None,
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 1x05 [720p].mkv",
+            title: "Show Name",
+            season: Some(1),
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "Show.Name.E05.1080p.mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: None,
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 13.5 (Recap) [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(13.5),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group]Show Name - 05[1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group][Show Name][05][720p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("720p"),
+            checksum: None,
+        },
+        Case {
+            input: "/home/user/Videos/Anime/[Group] Show Name - 07 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(7.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "C:\\Users\\user\\Videos\\[Group] Show Name - 08 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(8.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Re Zero kara Hajimeru Isekai Seikatsu - 05 [1080p].mkv",
+            title: "Re Zero kara Hajimeru Isekai Seikatsu",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name 05 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name 05v3 [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: Some(3),
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name S3 05 [1080p].mkv",
+            title: "Show Name",
+            season: Some(3),
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05 [1080p] [Dual Audio].mkv",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name [1080p].mkv",
+            title: "Show Name",
+            season: None,
+            episode: None,
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "Show Name S01E01-E02 1080p.mkv",
+            title: "Show Name",
+            season: Some(1),
+            episode: Some(1.0),
+            episode_end: Some(2.0),
+            version: None,
+            group: None,
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+        Case {
+            input: "[Group] Show Name - 05 【1080p】.MKV",
+            title: "Show Name",
+            season: None,
+            episode: Some(5.0),
+            episode_end: None,
+            version: None,
+            group: Some("Group"),
+            resolution: Some("1080p"),
+            checksum: None,
+        },
+    ];
+
+    #[test]
+    fn parse_filenames() {
+        let mut failures = Vec::new();
+        for case in CASES {
+            let expected = ParsedFilename {
+                title: case.title.to_string(),
+                season: case.season,
+                episode: case.episode,
+                episode_end: case.episode_end,
+                version: case.version,
+                release_group: case.group.map(String::from),
+                resolution: case.resolution.map(String::from),
+                checksum: case.checksum.map(String::from),
+                extension: split_extension(case.input).1.map(String::from),
+            };
+            let actual = parse_filename(case.input);
+            if actual.as_ref() != Some(&expected) {
+                failures.push(format!(
+                    "{}\n  expected {:?}\n  actual   {:?}",
+                    case.input, expected, actual
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn video_files() {
+        assert!(is_video_file("/videos/[Group] Show - 01.mkv"));
+        assert!(is_video_file("Show - 01.MP4"));
+        assert!(!is_video_file("Show Episode 1, Watch on Crunchyroll"));
+        assert!(!is_video_file("notes.txt"));
+    }
+
+    #[test]
+    fn rejects_empty_titles() {
+        assert_eq!(parse_filename("[Group] - 05 [1080p].mkv"), None);
+        assert_eq!(parse_filename(".mkv"), None);
+    }
+}
@@ -1,5 +1,22 @@
+pub mod episode;
+pub mod filename;
+#[cfg(all(target_os = "linux", feature = "mpris"))]
+pub mod mpris;
+#[cfg(unix)]
+pub mod mpv;
+pub mod normalize;
 pub mod parse;
+pub mod reload;
+pub mod source;
 pub mod window;
 
+pub use self::episode::*;
+#[cfg(all(target_os = "linux", feature = "mpris"))]
+pub use self::mpris::*;
+#[cfg(unix)]
+pub use self::mpv::*;
+pub use self::normalize::*;
 pub use self::parse::*;
+pub use self::reload::*;
+pub use self::source::*;
 pub use self::window::*;
@@ -0,0 +1,283 @@
+use super::Playback;
+use anyhow::Result;
+use dbus::{
+    arg::{PropMap, RefArg},
+    blocking::{stdintf::org_freedesktop_dbus::Properties, Connection},
+};
+use log::warn;
+use std::time::Duration;
+use url::Url;
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl PlaybackStatus {
+    fn from_str(status: &str) -> Self {
+        match status {
+            "Playing" => PlaybackStatus::Playing,
+            "Paused" => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Stopped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MprisPlayer {
+    pub bus_name: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub position: Option<Duration>,
+    pub length: Option<Duration>,
+    pub status: PlaybackStatus,
+}
+
+impl MprisPlayer {
+    /// The file name of the media being played, if the player reports a local `file://` url
+    pub fn file_name(&self) -> Option<String> {
+        let url = Url::parse(self.url.as_ref()?).ok()?;
+        if url.scheme() != "file" {
+            return None;
+        }
+        let path = url.to_file_path().ok()?;
+        Some(path.file_name()?.to_string_lossy().into_owned())
+    }
+
+    /// Strings that can be handed to the media parser, the title is tried before the file name
+    pub fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if let Some(title) = &self.title {
+            if !title.is_empty() {
+                candidates.push(title.clone());
+            }
+        }
+        if let Some(file_name) = self.file_name() {
+            if !candidates.contains(&file_name) {
+                candidates.push(file_name);
+            }
+        }
+        candidates
+    }
+
+    pub fn playback(&self) -> Playback {
+        let percent = match (self.position, self.length) {
+            (Some(position), Some(length)) if length.as_secs_f64() > 0.0 => {
+                Some(position.as_secs_f64() / length.as_secs_f64() * 100.0)
+            }
+            _ => None,
+        };
+        Playback {
+            percent,
+            paused: self.status != PlaybackStatus::Playing,
+        }
+    }
+}
+
+pub fn get_mpris_players() -> Vec<MprisPlayer> {
+    let players = Connection::new_session()
+        .map_err(anyhow::Error::from)
+        .and_then(|conn| get_mpris_players_on(&conn));
+    match players {
+        Ok(players) => players,
+        Err(err) => {
+            warn!("could not read mpris players: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+pub fn get_mpris_players_on(conn: &Connection) -> Result<Vec<MprisPlayer>> {
+    let bus = conn.with_proxy("org.freedesktop.DBus", "/", TIMEOUT);
+    let (names,): (Vec<String>,) = bus.method_call("org.freedesktop.DBus", "ListNames", ())?;
+    let mut players = Vec::new();
+    for name in names
+        .into_iter()
+        .filter(|name| name.starts_with(MPRIS_PREFIX))
+    {
+        match read_player(conn, name.clone()) {
+            Ok(player) => players.push(player),
+            Err(err) => warn!("could not read mpris player {}: {}", name, err),
+        }
+    }
+    Ok(players)
+}
+
+fn read_player(conn: &Connection, bus_name: String) -> Result<MprisPlayer> {
+    let proxy = conn.with_proxy(bus_name.as_str(), MPRIS_PATH, TIMEOUT);
+    let metadata: PropMap = proxy.get(PLAYER_INTERFACE, "Metadata")?;
+    let status: String = proxy.get(PLAYER_INTERFACE, "PlaybackStatus")?;
+    // Not every player implements Position, so it is allowed to fail
+    let position: Option<i64> = proxy.get(PLAYER_INTERFACE, "Position").ok();
+
+    let title = metadata
+        .get("xesam:title")
+        .and_then(|v| v.0.as_str())
+        .map(String::from);
+    let url = metadata
+        .get("xesam:url")
+        .and_then(|v| v.0.as_str())
+        .map(String::from);
+    let length = metadata.get("mpris:length").and_then(|v| v.0.as_i64());
+
+    Ok(MprisPlayer {
+        bus_name,
+        title,
+        url,
+        position: position.and_then(micros_to_duration),
+        length: length.and_then(micros_to_duration),
+        status: PlaybackStatus::from_str(&status),
+    })
+}
+
+fn micros_to_duration(micros: i64) -> Option<Duration> {
+    if micros >= 0 {
+        Some(Duration::from_micros(micros as u64))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbus::{
+        arg::Variant,
+        channel::{Channel, MatchingReceiver},
+        message::MatchRule,
+        Message,
+    };
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        thread,
+    };
+
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// Starts a throwaway session bus
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon has to be installed");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.as_mut().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            PrivateBus {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+
+        fn connect(&self) -> Connection {
+            let mut channel = Channel::open_private(&self.address).unwrap();
+            channel.register().unwrap();
+            Connection::from(channel)
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+        }
+    }
+
+    fn player_property(name: &str) -> Option<Variant<Box<dyn RefArg>>> {
+        let value: Box<dyn RefArg> = match name {
+            "Metadata" => {
+                let mut metadata: PropMap = HashMap::new();
+                metadata.insert(
+                    "xesam:title".to_string(),
+                    Variant(Box::new("Show Name - 05".to_string())),
+                );
+                metadata.insert(
+                    "xesam:url".to_string(),
+                    Variant(Box::new(
+                        "file:///videos/%5BGroup%5D%20Show%20Name%20-%2005.mkv".to_string(),
+                    )),
+                );
+                metadata.insert(
+                    "mpris:length".to_string(),
+                    Variant(Box::new(1_440_000_000i64)),
+                );
+                Box::new(metadata)
+            }
+            "PlaybackStatus" => Box::new("Paused".to_string()),
+            "Position" => Box::new(720_000_000i64),
+            _ => return None,
+        };
+        Some(Variant(value))
+    }
+
+    fn serve_fake_player(bus: &PrivateBus) {
+        let conn = bus.connect();
+        conn.request_name("org.mpris.MediaPlayer2.fake", false, true, false)
+            .unwrap();
+        conn.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(|msg: Message, conn| {
+                let (_, name): (Option<String>, Option<String>) = msg.get2();
+                let reply = match name.as_deref().and_then(player_property) {
+                    Some(value) => msg.method_return().append1(value),
+                    None => Message::error(
+                        &msg,
+                        &"org.freedesktop.DBus.Error.UnknownProperty".into(),
+                        &std::ffi::CString::new("unknown property").unwrap(),
+                    ),
+                };
+                let _ = dbus::channel::Sender::send(conn, reply);
+                true
+            }),
+        );
+        thread::spawn(move || loop {
+            if conn.process(Duration::from_millis(100)).is_err() {
+                break;
+            }
+        });
+    }
+
+    #[test]
+    #[ignore = "starts a dbus-daemon, run with --ignored where one is installed"]
+    fn reads_fake_player() {
+        let bus = PrivateBus::start();
+        serve_fake_player(&bus);
+
+        let players = get_mpris_players_on(&bus.connect()).unwrap();
+        assert_eq!(players.len(), 1);
+        let player = &players[0];
+        assert_eq!(player.bus_name, "org.mpris.MediaPlayer2.fake");
+        assert_eq!(player.title.as_deref(), Some("Show Name - 05"));
+        assert_eq!(player.status, PlaybackStatus::Paused);
+        assert_eq!(player.position, Some(Duration::from_secs(720)));
+        assert_eq!(player.length, Some(Duration::from_secs(1440)));
+        assert_eq!(
+            player.playback(),
+            Playback {
+                percent: Some(50.0),
+                paused: true
+            }
+        );
+        assert_eq!(
+            player.candidates(),
+            vec![
+                "Show Name - 05".to_string(),
+                "[Group] Show Name - 05.mkv".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use super::Playback;
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::{json, Value};
+use std::{path::Path, time::Duration};
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    net::UnixStream,
+    time,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpvStatus {
+    pub socket: String,
+    pub path: Option<String>,
+    pub media_title: Option<String>,
+    pub percent_pos: Option<f64>,
+    pub paused: bool,
+}
+
+impl MpvStatus {
+    /// Strings that can be handed to the media parser, the media title is tried before the file name
+    pub fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if let Some(title) = &self.media_title {
+            if !title.is_empty() {
+                candidates.push(title.clone());
+            }
+        }
+        let file_name = self
+            .path
+            .as_ref()
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        if let Some(file_name) = file_name {
+            if !candidates.contains(&file_name) {
+                candidates.push(file_name);
+            }
+        }
+        candidates
+    }
+
+    pub fn playback(&self) -> Playback {
+        Playback {
+            percent: self.percent_pos,
+            paused: self.paused,
+        }
+    }
+}
+
+struct MpvClient {
+    reader: BufReader<ReadHalf<UnixStream>>,
+    writer: WriteHalf<UnixStream>,
+    next_id: u64,
+}
+
+impl MpvClient {
+    fn new(stream: UnixStream) -> Self {
+        let (reader, writer) = io::split(stream);
+        MpvClient {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 1,
+        }
+    }
+
+    /// Returns None when mpv reports the property as unavailable, e.g. when nothing is loaded
+    async fn get_property(&mut self, name: &str) -> Result<Option<Value>> {
+        let request_id = self.next_id;
+        self.next_id += 1;
+        let command = json!({ "command": ["get_property", name], "request_id": request_id });
+        let mut line = command.to_string();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+
+        loop {
+            let mut response = String::new();
+            if self.reader.read_line(&mut response).await? == 0 {
+                return Err(anyhow!("mpv closed the connection"));
+            }
+            let response: Value = serde_json::from_str(&response)?;
+            // Skip over events and replies to other requests
+            if response.get("request_id").and_then(Value::as_u64) != Some(request_id) {
+                continue;
+            }
+            return match response.get("error").and_then(Value::as_str) {
+                Some("success") => Ok(response.get("data").cloned()),
+                Some(err) => {
+                    debug!("mpv property {} is not available: {}", name, err);
+                    Ok(None)
+                }
+                None => Err(anyhow!("mpv response is missing an error field")),
+            };
+        }
+    }
+}
+
+pub async fn query_mpv(socket: &str) -> Result<MpvStatus> {
+    let query = async {
+        let stream = UnixStream::connect(socket).await?;
+        let mut client = MpvClient::new(stream);
+        let path = client.get_property("path").await?;
+        let media_title = client.get_property("media-title").await?;
+        let percent_pos = client.get_property("percent-pos").await?;
+        let pause = client.get_property("pause").await?;
+        Ok(MpvStatus {
+            socket: socket.to_string(),
+            path: path.as_ref().and_then(Value::as_str).map(String::from),
+            media_title: media_title
+                .as_ref()
+                .and_then(Value::as_str)
+                .map(String::from),
+            percent_pos: percent_pos.as_ref().and_then(Value::as_f64),
+            paused: pause.as_ref().and_then(Value::as_bool).unwrap_or(false),
+        })
+    };
+    match time::timeout(TIMEOUT, query).await {
+        Ok(status) => status,
+        Err(_) => Err(anyhow!("timed out querying mpv at {}", socket)),
+    }
+}
+
+pub async fn get_mpv_players(sockets: &[String]) -> Vec<MpvStatus> {
+    let mut players = Vec::new();
+    for socket in sockets {
+        match query_mpv(socket).await {
+            // mpv keeps the socket open while idle, so skip it if there is no file loaded
+            Ok(status) if status.path.is_some() => players.push(status),
+            Ok(_) => {}
+            Err(err) => debug!("could not query mpv at {}: {}", socket, err),
+        }
+    }
+    players
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    async fn serve_fake_mpv(listener: &mut UnixListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = io::split(stream);
+        let mut reader = BufReader::new(reader);
+        writer
+            .write_all(b"{\"event\":\"playback-restart\"}\n")
+            .await
+            .unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let data = match request["command"][1].as_str().unwrap() {
+                "path" => json!("/videos/[Group] Show Name - 05 (1080p).mkv"),
+                "media-title" => json!("Show Name - 05"),
+                "percent-pos" => json!(42.5),
+                "pause" => json!(true),
+                _ => Value::Null,
+            };
+            let response = json!({
+                "data": data,
+                "error": "success",
+                "request_id": request["request_id"],
+            });
+            let response = format!("{}\n", response);
+            writer.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_fake_mpv() {
+        let path = std::env::temp_dir().join(format!("fubuki-mpv-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move { serve_fake_mpv(&mut listener).await });
+
+        let socket = path.to_string_lossy().into_owned();
+        let players = get_mpv_players(&[socket.clone()]).await;
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            players,
+            vec![MpvStatus {
+                socket,
+                path: Some("/videos/[Group] Show Name - 05 (1080p).mkv".to_string()),
+                media_title: Some("Show Name - 05".to_string()),
+                percent_pos: Some(42.5),
+                paused: true,
+            }]
+        );
+        assert_eq!(
+            players[0].candidates(),
+            vec![
+                "Show Name - 05".to_string(),
+                "[Group] Show Name - 05 (1080p).mkv".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{anilist::MediaFormat, settings};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+static FORMAT_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\s*[(\[](TV Short|TV|Movie|OVA|ONA|Special)[)\]]").unwrap());
+static PART: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)[\s:,-]*\b(?:(?:part|cour)\s*(?P<number>\d+|[ivx]+)|(?P<ordinal>\d+)(?:st|nd|rd|th)\s+(?:part|cour))\b",
+    )
+    .unwrap()
+});
+static SEASON: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)[\s:,-]*\b(?:season\s*(?P<number>\d+|[ivx]+)|(?P<ordinal>\d+)(?:st|nd|rd|th)\s+season|(?P<word>first|second|third|fourth|fifth|sixth|seventh|eighth|ninth|tenth)\s+season)\b",
+    )
+    .unwrap()
+});
+/// Sequels like "Show Name II", only uppercase so words like "i" aren't taken as numerals. I, V and
+/// X are left out since titles like "Mobile Suit Gundam X" end in them.
+static TRAILING_ROMAN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+(II|III|IV)$").unwrap());
+static WHITESPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+
+const ORDINALS: [&str; 10] = [
+    "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth", "tenth",
+];
+
+/// Which steps of title normalization run before searching and matching
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizeSettings {
+    pub case_folding: bool,
+    pub punctuation: bool,
+    pub diacritics: bool,
+    /// Pull season, part and format suffixes out of the title into a `SeasonHint`
+    pub seasons: bool,
+    /// Read numerals like "Season II" and "Part IV"
+    pub roman_numerals: bool,
+    /// Take a title ending in II, III or IV as that season. Off by default since some titles
+    /// just end in one.
+    pub trailing_roman_numerals: bool,
+    pub fullwidth: bool,
+}
+
+impl Default for NormalizeSettings {
+    fn default() -> Self {
+        NormalizeSettings {
+            case_folding: true,
+            punctuation: true,
+            diacritics: true,
+            seasons: true,
+            roman_numerals: true,
+            trailing_roman_numerals: false,
+            fullwidth: true,
+        }
+    }
+}
+
+/// What the title said about which entry of a franchise it is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeasonHint {
+    pub season: Option<u32>,
+    pub part: Option<u32>,
+    pub format: Option<MediaFormat>,
+}
+
+impl SeasonHint {
+    pub fn is_empty(&self) -> bool {
+        self.season.is_none() && self.part.is_none() && self.format.is_none()
+    }
+
+    /// Titles without a season are the first one
+    pub fn same_season(&self, other: &SeasonHint) -> bool {
+        self.season.unwrap_or(1) == other.season.unwrap_or(1)
+            && self.part.unwrap_or(1) == other.part.unwrap_or(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedTitle {
+    /// The title with season suffixes removed, for sending to the search
+    pub query: String,
+    /// The query folded down for comparing against other titles
+    pub key: String,
+    pub hint: SeasonHint,
+}
+
+impl NormalizedTitle {
+    /// The key with the season, part and format put back, so a media remembered for
+    /// "Show Season 2" isn't used for "Show" or "Show Season 3"
+    pub fn alias_key(&self) -> String {
+        let mut key = self.key.clone();
+        // No season or part is the first one, the same as `SeasonHint::same_season`
+        if let Some(season) = self.hint.season.filter(|season| *season != 1) {
+            key.push_str(&format!(" season {}", season));
+        }
+        if let Some(part) = self.hint.part.filter(|part| *part != 1) {
+            key.push_str(&format!(" part {}", part));
+        }
+        if let Some(format) = &self.hint.format {
+            key.push_str(&format!(" ({})", format.str().to_lowercase()));
+        }
+        key
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Normalizer {
+    settings: NormalizeSettings,
+}
+
+/// Normalizes the title with the steps enabled in the settings
+pub fn normalize_title(title: &str) -> NormalizedTitle {
+    let settings = settings::get_settings().read().unwrap().normalize;
+    Normalizer::new(settings).normalize(title)
+}
+
+impl Normalizer {
+    pub fn new(settings: NormalizeSettings) -> Self {
+        Normalizer { settings }
+    }
+
+    pub fn normalize(&self, title: &str) -> NormalizedTitle {
+        let mut query = title.to_string();
+        if self.settings.fullwidth {
+            query = query.chars().map(fullwidth_to_ascii).collect();
+        }
+        if self.settings.diacritics {
+            query = query.nfd().filter(|c| !is_combining_mark(*c)).collect();
+        }
+
+        let mut hint = SeasonHint::default();
+        if self.settings.seasons {
+            query = self.extract_hints(&query, &mut hint);
+        }
+        query = collapse_whitespace(&query)
+            .trim_end_matches(|c: char| c == '-' || c == ':' || c == ',' || c.is_whitespace())
+            .to_string();
+
+        let mut key = query.clone();
+        if self.settings.punctuation {
+            key = key
+                .chars()
+                .filter(|c| *c != '\'' && *c != '’')
+                .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+                .collect();
+        }
+        if self.settings.case_folding {
+            key = key.to_lowercase();
+        }
+
+        NormalizedTitle {
+            query,
+            key: collapse_whitespace(&key),
+            hint,
+        }
+    }
+
+    fn extract_hints(&self, title: &str, hint: &mut SeasonHint) -> String {
+        let mut title = FORMAT_TAG
+            .replace_all(title, |caps: &Captures<'_>| {
+                hint.format = match caps[1].to_lowercase().as_str() {
+                    "tv" => Some(MediaFormat::Tv),
+                    "tv short" => Some(MediaFormat::TvShort),
+                    "movie" => Some(MediaFormat::Movie),
+                    "ova" => Some(MediaFormat::Ova),
+                    "ona" => Some(MediaFormat::Ona),
+                    _ => Some(MediaFormat::Special),
+                };
+                ""
+            })
+            .into_owned();
+        title = PART
+            .replace_all(&title, |caps: &Captures<'_>| {
+                hint.part = self.capture_number(caps);
+                ""
+            })
+            .into_owned();
+        title = SEASON
+            .replace_all(&title, |caps: &Captures<'_>| {
+                hint.season = self.capture_number(caps);
+                ""
+            })
+            .into_owned();
+
+        if self.settings.trailing_roman_numerals && hint.season.is_none() {
+            let trimmed = title.trim_end().to_string();
+            if let Some(caps) = TRAILING_ROMAN.captures(&trimmed) {
+                hint.season = roman_to_int(&caps[1]);
+                title = trimmed[..caps.get(0).unwrap().start()].to_string();
+            }
+        }
+        title
+    }
+
+    fn capture_number(&self, caps: &Captures<'_>) -> Option<u32> {
+        if let Some(n) = caps.name("number").or_else(|| caps.name("ordinal")) {
+            let n = n.as_str();
+            return match n.parse::<u32>() {
+                Ok(n) => Some(n),
+                Err(_) if self.settings.roman_numerals => roman_to_int(n),
+                Err(_) => None,
+            };
+        }
+        let word = caps.name("word")?.as_str().to_lowercase();
+        ORDINALS
+            .iter()
+            .position(|ordinal| *ordinal == word)
+            .map(|i| i as u32 + 1)
+    }
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    WHITESPACE.replace_all(s.trim(), " ").into_owned()
+}
+
+fn fullwidth_to_ascii(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => std::char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{3000}' => ' ',
+        _ => c,
+    }
+}
+
+fn roman_to_int(numeral: &str) -> Option<u32> {
+    let value = |c| match c {
+        'i' | 'I' => Some(1),
+        'v' | 'V' => Some(5),
+        'x' | 'X' => Some(10),
+        _ => None,
+    };
+    let digits = numeral.chars().map(value).collect::<Option<Vec<u32>>>()?;
+    let mut total = 0;
+    for (i, digit) in digits.iter().enumerate() {
+        match digits.get(i + 1) {
+            Some(next) if next > digit => total -= *digit as i32,
+            _ => total += *digit as i32,
+        }
+    }
+    if total > 0 {
+        Some(total as u32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_titles() {
+        let normalizer = Normalizer::default();
+        let season = |season| SeasonHint {
+            season,
+            ..SeasonHint::default()
+        };
+        let cases = vec![
+            ("Show Name", "Show Name", "show name", season(None)),
+            (
+                "Show Name Season 2",
+                "Show Name",
+                "show name",
+                season(Some(2)),
+            ),
+            (
+                "Show Name 2nd Season",
+                "Show Name",
+                "show name",
+                season(Some(2)),
+            ),
+            (
+                "Show Name: Second Season",
+                "Show Name",
+                "show name",
+                season(Some(2)),
+            ),
+            (
+                "Show Name Season II",
+                "Show Name",
+                "show name",
+                season(Some(2)),
+            ),
+            (
+                "SHOW NAME (TV)",
+                "SHOW NAME",
+                "show name",
+                SeasonHint {
+                    format: Some(MediaFormat::Tv),
+                    ..SeasonHint::default()
+                },
+            ),
+            (
+                "Show Name Season 3 Part 2",
+                "Show Name",
+                "show name",
+                SeasonHint {
+                    season: Some(3),
+                    part: Some(2),
+                    format: None,
+                },
+            ),
+            ("Ｓｈｏｗ　Ｎａｍｅ", "Show Name", "show name", season(None)),
+            ("Pokémon", "Pokemon", "pokemon", season(None)),
+            (
+                "Kaguya-sama: Love is War",
+                "Kaguya-sama: Love is War",
+                "kaguya sama love is war",
+                season(None),
+            ),
+            (
+                "Don't Toy with Me",
+                "Don't Toy with Me",
+                "dont toy with me",
+                season(None),
+            ),
+            (
+                "Mobile Suit Gundam X",
+                "Mobile Suit Gundam X",
+                "mobile suit gundam x",
+                season(None),
+            ),
+            ("Show Name II", "Show Name II", "show name ii", season(None)),
+        ];
+        for (title, query, key, hint) in cases {
+            let normalized = normalizer.normalize(title);
+            assert_eq!(normalized.query, query, "query of {}", title);
+            assert_eq!(normalized.key, key, "key of {}", title);
+            assert_eq!(normalized.hint, hint, "hint of {}", title);
+        }
+    }
+
+    #[test]
+    fn reads_trailing_numerals_when_enabled() {
+        let normalizer = Normalizer::new(NormalizeSettings {
+            trailing_roman_numerals: true,
+            ..NormalizeSettings::default()
+        });
+        let season = |title| normalizer.normalize(title).hint.season;
+        assert_eq!(season("Mob Psycho 100 II"), Some(2));
+        assert_eq!(
+            normalizer.normalize("Mob Psycho 100 II").query,
+            "Mob Psycho 100"
+        );
+        assert_eq!(season("Show Name IV"), Some(4));
+        assert_eq!(season("Mobile Suit Gundam X"), None);
+        assert_eq!(season("Vivy V"), None);
+    }
+
+    #[test]
+    fn steps_can_be_disabled() {
+        let normalizer = Normalizer::new(NormalizeSettings {
+            seasons: false,
+            case_folding: false,
+            ..NormalizeSettings::default()
+        });
+        let normalized = normalizer.normalize("Show Name Season 2");
+        assert_eq!(normalized.key, "Show Name Season 2");
+        assert!(normalized.hint.is_empty());
+    }
+}
@@ -1,142 +1,356 @@
-use super::get_window_titles;
-use crate::settings;
+use super::filename::{is_video_file, parse_filename};
+use super::{Episode, Hint, MediaSources};
+use crate::anilist::MediaType;
+use crate::settings::{self, ExpectedMatch, RecognitionRule};
 use anyhow::Result;
 use log::warn;
-use once_cell::sync::Lazy;
 use regex::{Captures, Regex, RegexSet};
-use std::collections::HashMap;
+use std::{cmp::Reverse, fmt, num::ParseFloatError};
 
-static MEDIA_PARSER: Lazy<MediaParser> = Lazy::new(|| {
-    let settings = settings::get_settings().read().unwrap();
-    MediaParser::new(&settings.recognition.anime, &settings.recognition.manga).unwrap_or_default()
-});
+/// Reported as the matched rule for media found by the file name parser
+pub const FILENAME_RULE: &str = "filename";
 
-#[derive(Default, Clone, Debug)]
-pub struct MediaParser {
-    anime: Option<RegexSet>,
-    manga: Option<RegexSet>,
-    cache: HashMap<String, Regex>,
+#[derive(Clone, Debug)]
+pub struct CompiledRule {
+    pub id: String,
+    pub media_type: MediaType,
+    regex: Regex,
+    cleanup: Vec<(Regex, String)>,
 }
 
-impl MediaParser {
-    pub fn new(anime_regexes: &Vec<String>, manga_regexes: &Vec<String>) -> Result<Self> {
-        let mut cache = HashMap::new();
-        for pattern in anime_regexes.iter() {
-            let regex = Regex::new(pattern)?;
-            cache.insert(pattern.clone(), regex);
+impl CompiledRule {
+    pub fn new(rule: &RecognitionRule) -> Result<Self, RuleError> {
+        let error = |message| RuleError {
+            rule: rule.id.clone(),
+            message,
+        };
+        let regex = Regex::new(&rule.regex).map_err(|err| error(err.to_string()))?;
+        let mut cleanup = Vec::new();
+        for substitution in rule.cleanup.iter() {
+            let pattern = Regex::new(&substitution.pattern)
+                .map_err(|err| error(format!("cleanup: {}", err)))?;
+            cleanup.push((pattern, substitution.replace.clone()));
         }
-        for pattern in manga_regexes.iter() {
-            let regex = Regex::new(pattern)?;
-            cache.insert(pattern.clone(), regex);
+        Ok(CompiledRule {
+            id: rule.id.clone(),
+            media_type: rule.media_type,
+            regex,
+            cleanup,
+        })
+    }
+
+    pub fn parse(&self, window_title: &str) -> Option<Media> {
+        let (media, errors) = self.parse_with_errors(window_title)?;
+        for err in errors {
+            warn!("{}", err);
         }
+        Some(media)
+    }
 
-        Ok(MediaParser {
-            anime: Some(RegexSet::new(anime_regexes)?),
-            manga: Some(RegexSet::new(manga_regexes)?),
-            cache,
-        })
+    /// Parses the title, also returning captures that matched but could not be read as numbers
+    pub fn parse_with_errors(&self, window_title: &str) -> Option<(Media, Vec<String>)> {
+        let captures = self.regex.captures(window_title)?;
+        let mut title = captures.name("title")?.as_str().to_string();
+        for (pattern, replace) in self.cleanup.iter() {
+            title = pattern.replace_all(&title, replace.as_str()).into_owned();
+        }
+        let mut errors = Vec::new();
+        let mut capture = |name| match parse_capture(&captures, name) {
+            Ok(n) => n,
+            Err(err) => {
+                errors.push(format!(
+                    "rule {}: could not parse {}: {}",
+                    self.id, name, err
+                ));
+                None
+            }
+        };
+        let (progress, progress_end, progress_volumes) = match self.media_type {
+            MediaType::Anime => (capture("episode"), capture("episode_end"), None),
+            MediaType::Manga => (
+                capture("chapter"),
+                capture("chapter_end"),
+                capture("volume"),
+            ),
+        };
+        let version = capture("version").map(|version| version as i32);
+        let media = Media {
+            title: title.trim().to_string(),
+            media_type: self.media_type,
+            progress,
+            progress_end,
+            version,
+            progress_volumes,
+            playback: None,
+            rule: Some(self.id.clone()),
+        };
+        Some((media, errors))
+    }
+}
+
+fn parse_capture(captures: &Captures<'_>, name: &str) -> Result<Option<f64>, ParseFloatError> {
+    match captures.name(name) {
+        Some(p) => p.as_str().parse::<f64>().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// A rule that could not be compiled
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleError {
+    pub rule: String,
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {}: {}", self.rule, self.message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExampleFailure {
+    pub rule: String,
+    pub title: String,
+    pub expected: Option<ExpectedMatch>,
+    pub actual: Option<ExpectedMatch>,
+}
+
+impl fmt::Display for ExampleFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rule {} on \"{}\": expected {:?}, got {:?}",
+            self.rule, self.title, self.expected, self.actual
+        )
     }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct MediaParser {
+    set: Option<RegexSet>,
+    rules: Vec<CompiledRule>,
+}
 
-    pub fn match_set<'a>(&'a self, regex_set: &'a RegexSet, window_title: &str) -> Option<&'a str> {
-        let mut matches = regex_set.matches(window_title).into_iter();
-        let match_ndx = matches.next()?;
-        Some(&regex_set.patterns()[match_ndx])
+impl MediaParser {
+    /// Fails on the first rule that doesn't compile
+    pub fn new(rules: &[RecognitionRule]) -> Result<Self> {
+        let (parser, errors) = Self::with_errors(rules);
+        match errors.into_iter().next() {
+            Some(err) => Err(err.into()),
+            None => Ok(parser),
+        }
     }
 
-    pub fn parse_anime<'a>(&self, window_title: &'a str) -> Option<Captures<'a>> {
-        let set = self.anime.as_ref()?;
-        self.parse_media(set, window_title)
+    /// Builds a parser out of every rule that compiles, returning the errors of the ones that don't
+    pub fn with_errors(rules: &[RecognitionRule]) -> (Self, Vec<RuleError>) {
+        let mut rules: Vec<&RecognitionRule> = rules.iter().filter(|rule| rule.enabled).collect();
+        rules.sort_by_key(|rule| Reverse(rule.priority));
+        let mut compiled = Vec::new();
+        let mut errors = Vec::new();
+        for rule in rules {
+            match CompiledRule::new(rule) {
+                Ok(rule) => compiled.push(rule),
+                Err(err) => errors.push(err),
+            }
+        }
+        // Every pattern already compiled on its own, so only size limits can fail here
+        let set = match RegexSet::new(compiled.iter().map(|rule| rule.regex.as_str())) {
+            Ok(set) => Some(set),
+            Err(err) => {
+                errors.push(RuleError {
+                    rule: "*".to_string(),
+                    message: err.to_string(),
+                });
+                None
+            }
+        };
+        (
+            MediaParser {
+                set,
+                rules: compiled,
+            },
+            errors,
+        )
     }
 
-    pub fn parse_manga<'a>(&self, window_title: &'a str) -> Option<Captures<'a>> {
-        let set = self.manga.as_ref()?;
-        self.parse_media(set, window_title)
+    /// The highest priority rule matching the title
+    pub fn match_rule(&self, window_title: &str) -> Option<&CompiledRule> {
+        let index = self
+            .set
+            .as_ref()?
+            .matches(window_title)
+            .into_iter()
+            .next()?;
+        self.rules.get(index)
     }
 
-    pub fn parse_media<'a>(
-        &self,
-        regex_set: &RegexSet,
-        window_title: &'a str,
-    ) -> Option<Captures<'a>> {
-        let pattern = self.match_set(regex_set, window_title)?;
-        if let Some(regex) = self.cache.get(pattern) {
-            regex.captures(window_title)
-        } else {
-            None
+    /// Checks every example of the enabled rules, returning the ones that did not parse as expected
+    pub fn verify_examples(rules: &[RecognitionRule]) -> Result<Vec<ExampleFailure>> {
+        let mut failures = Vec::new();
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            let compiled = CompiledRule::new(rule)?;
+            for example in rule.examples.iter() {
+                let actual = compiled
+                    .parse(&example.title)
+                    .map(|media| media.expected_match());
+                if actual != example.expected {
+                    failures.push(ExampleFailure {
+                        rule: rule.id.clone(),
+                        title: example.title.clone(),
+                        expected: example.expected.clone(),
+                        actual,
+                    });
+                }
+            }
         }
+        Ok(failures)
     }
 
     pub async fn detect_media() -> Option<Media> {
-        for title in get_window_titles() {
-            if let Some(anime_captures) = MEDIA_PARSER.parse_anime(&title) {
-                let episode = match anime_captures.name("episode") {
-                    Some(p) => match p.as_str().parse::<f64>() {
-                        Ok(ep) => Some(ep),
-                        Err(err) => {
-                            warn!("could not parse episode: {}", err);
-                            None
-                        }
-                    },
-                    None => None,
-                };
-                let media = Media {
-                    title: String::from(anime_captures.name("title")?.as_str()),
-                    media_type: crate::anilist::MediaType::Anime,
-                    progress: episode,
-                    progress_volumes: None,
-                };
-                return Some(media);
-            } else if let Some(manga_captures) = MEDIA_PARSER.parse_manga(&title) {
-                let chapter = match manga_captures.name("chapter") {
-                    Some(p) => match p.as_str().parse::<f64>() {
-                        Ok(ep) => Some(ep),
-                        Err(err) => {
-                            warn!("could not parse chapter: {}", err);
-                            None
-                        }
-                    },
-                    None => None,
-                };
-                let volume = match manga_captures.name("volume") {
-                    Some(p) => match p.as_str().parse::<f64>() {
-                        Ok(ep) => Some(ep),
-                        Err(err) => {
-                            warn!("could not parse volume: {}", err);
-                            None
-                        }
-                    },
-                    None => None,
-                };
-                let media = Media {
-                    title: String::from(manga_captures.name("title")?.as_str()),
-                    media_type: crate::anilist::MediaType::Manga,
-                    progress: chapter,
-                    progress_volumes: volume,
-                };
+        let sources = {
+            let settings = settings::get_settings().read().unwrap();
+            MediaSources::from_settings(&settings.sources, &settings.mpv_sockets)
+        };
+        super::reload_if_changed();
+        super::media_parser().detect_from(&sources).await
+    }
+
+    /// Returns the first media found in the candidates of the sources, by priority
+    pub async fn detect_from(&self, sources: &MediaSources) -> Option<Media> {
+        for candidate in sources.candidates().await {
+            let media = match candidate.hint {
+                Hint::Text(text) => self.parse_title(&text),
+                Hint::Media(media) => Some(media),
+            };
+            if let Some(mut media) = media {
+                if media.playback.is_none() {
+                    media.playback = candidate.playback;
+                }
                 return Some(media);
             }
         }
-
         None
     }
+
+    pub fn parse_title(&self, title: &str) -> Option<Media> {
+        match self.match_rule(title) {
+            Some(rule) => rule.parse(title),
+            None if is_video_file(title) => Self::parse_video_file(title),
+            None => None,
+        }
+    }
+
+    /// Same as `parse_title`, but hands back capture errors instead of logging them
+    pub fn parse_title_with_errors(&self, title: &str) -> (Option<Media>, Vec<String>) {
+        match self.match_rule(title) {
+            Some(rule) => match rule.parse_with_errors(title) {
+                Some((media, errors)) => (Some(media), errors),
+                None => (None, vec![format!("rule {} has no title capture", rule.id)]),
+            },
+            None if is_video_file(title) => (Self::parse_video_file(title), Vec::new()),
+            None => (None, Vec::new()),
+        }
+    }
+
+    /// Parses file names that site rules can't handle, like the ones local players report
+    pub fn parse_video_file(path: &str) -> Option<Media> {
+        let parsed = parse_filename(path)?;
+        // Keep the season in the title so the search can find the right sequel
+        let title = match parsed.season {
+            Some(season) if season > 1 => format!("{} Season {}", parsed.title, season),
+            _ => parsed.title,
+        };
+        Some(Media {
+            title,
+            media_type: MediaType::Anime,
+            progress: parsed.episode,
+            progress_end: parsed.episode_end,
+            version: parsed.version,
+            progress_volumes: None,
+            playback: None,
+            rule: Some(FILENAME_RULE.to_string()),
+        })
+    }
 }
 
-use crate::anilist::MediaType;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playback {
+    /// How far through the file the player is, from 0 to 100
+    pub percent: Option<f64>,
+    pub paused: bool,
+}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Media {
     pub title: String,
     pub media_type: MediaType,
     pub progress: Option<f64>,
+    /// The last episode or chapter of a batch, see `Episode`
+    pub progress_end: Option<f64>,
+    pub version: Option<i32>,
     pub progress_volumes: Option<f64>,
+    pub playback: Option<Playback>,
+    /// Id of the recognition rule that matched
+    pub rule: Option<String>,
+}
+
+// Playback changes every time media is detected, the rule is only informational
+// and a new version is the same episode, so none are part of the media's identity
+impl PartialEq for Media {
+    fn eq(&self, other: &Self) -> bool {
+        self.title == other.title
+            && self.media_type == other.media_type
+            && self.progress == other.progress
+            && self.progress_end == other.progress_end
+            && self.progress_volumes == other.progress_volumes
+    }
 }
 
 impl Media {
+    /// The episode, or chapter for manga
+    pub fn episode(&self) -> Option<Episode> {
+        Some(Episode {
+            number: self.progress?,
+            end: self.progress_end,
+            version: self.version,
+        })
+    }
+
+    /// Renumbers the episode or chapter, moving the end of a batch along with it
+    pub fn set_progress(&mut self, progress: Option<f64>) {
+        let episode = self.episode();
+        match (episode, progress) {
+            (Some(episode), Some(progress)) => self.progress_end = episode.renumber(progress).end,
+            _ => self.progress_end = None,
+        }
+        self.progress = progress;
+    }
+
+    pub fn expected_match(&self) -> ExpectedMatch {
+        let (episode, episode_end, chapter, chapter_end) = match self.media_type {
+            MediaType::Anime => (self.progress, self.progress_end, None, None),
+            MediaType::Manga => (None, None, self.progress, self.progress_end),
+        };
+        ExpectedMatch {
+            title: self.title.clone(),
+            episode,
+            episode_end,
+            chapter,
+            chapter_end,
+            volume: self.progress_volumes,
+            version: self.version,
+        }
+    }
+
     // TODO: Check media format (doujin, movie, etc) when making this string
     pub fn current_media_string(&self) -> String {
         match &self.media_type {
-            MediaType::Anime => match self.progress {
-                Some(p) => format!("Watching Episode {}", p),
+            MediaType::Anime => match self.episode() {
+                Some(episode) if episode.is_range() => format!("Watching Episodes {}", episode),
+                Some(episode) => format!("Watching Episode {}", episode),
                 None => String::default(),
             },
             MediaType::Manga => {
@@ -144,11 +358,61 @@ impl Media {
                     Some(p) => format!("Reading Vol. {}", p as i32),
                     None => String::from("Reading"),
                 };
-                if let Some(p) = self.progress {
-                    s = format!("{} Ch. {}", s, p);
+                if let Some(chapter) = self.episode() {
+                    s = format!("{} Ch. {}", s, chapter);
                 }
                 return s;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::RecognitionData;
+
+    #[test]
+    fn bundled_rule_examples_match() {
+        let rules = RecognitionData::load().unwrap().rules;
+        let failures = MediaParser::verify_examples(&rules).unwrap();
+        assert!(
+            failures.is_empty(),
+            "{}",
+            failures
+                .iter()
+                .map(|failure| failure.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        );
+    }
+
+    #[test]
+    fn captures_batches_and_versions() {
+        let mut rule = RecognitionData::load().unwrap().rules[0].clone();
+        rule.media_type = MediaType::Anime;
+        rule.regex =
+            r"^(?P<title>.+) - (?P<episode>\d+)(-(?P<episode_end>\d+))?(v(?P<version>\d))?$"
+                .to_string();
+        rule.cleanup.clear();
+        let compiled = CompiledRule::new(&rule).unwrap();
+
+        let batch = compiled.parse("Show Name - 01-03").unwrap();
+        assert_eq!(batch.episode().unwrap().last(), 3.0);
+        assert_eq!(batch.current_media_string(), "Watching Episodes 1-3");
+
+        let mut version = compiled.parse("Show Name - 05v2").unwrap();
+        assert_eq!(version.version, Some(2));
+        assert_eq!(version.progress_end, None);
+        version.set_progress(Some(1.0));
+        assert_eq!(version.current_media_string(), "Watching Episode 1v2");
+    }
+
+    #[test]
+    fn invalid_rule_is_reported_by_id() {
+        let mut rules = RecognitionData::load().unwrap().rules;
+        rules[0].regex = "(?P<title>".to_string();
+        let err = MediaParser::new(&rules).unwrap_err();
+        assert!(err.to_string().contains(&rules[0].id));
+    }
+}
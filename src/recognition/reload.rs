@@ -0,0 +1,152 @@
+use super::MediaParser;
+use crate::settings::{self, RecognitionData};
+use anyhow::Result;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::{
+    fs,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+static MEDIA_PARSER: Lazy<RwLock<LoadedParser>> = Lazy::new(|| {
+    let settings = settings::get_settings().read().unwrap();
+    let mut loaded = LoadedParser::new(&settings.recognition);
+    loaded.custom_modified = custom_modified();
+    RwLock::new(loaded)
+});
+
+/// The parser currently used for detection, swapped out whole when the custom rules change
+pub fn media_parser() -> Arc<MediaParser> {
+    MEDIA_PARSER.read().unwrap().parser.clone()
+}
+
+/// Problems with the custom rules from the last time they were loaded
+pub fn rule_errors() -> Vec<String> {
+    MEDIA_PARSER.read().unwrap().errors.clone()
+}
+
+/// Rebuilds the parser if the custom rules file was created, changed or removed since it was last loaded
+pub fn reload_if_changed() {
+    let modified = custom_modified();
+    if MEDIA_PARSER.read().unwrap().custom_modified == modified {
+        return;
+    }
+
+    let bundled = match RecognitionData::load() {
+        Ok(bundled) => bundled,
+        Err(err) => {
+            warn!("could not load bundled recognition data: {}", err);
+            return;
+        }
+    };
+    let reloaded = {
+        let mut loaded = MEDIA_PARSER.write().unwrap();
+        loaded.custom_modified = modified;
+        loaded.reload(bundled, RecognitionData::load_custom())
+    };
+    if let Some(data) = reloaded {
+        info!("reloaded recognition rules");
+        settings::get_settings().write().unwrap().recognition = data;
+    }
+}
+
+fn custom_modified() -> Option<SystemTime> {
+    let path = RecognitionData::custom_path().ok()?;
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[derive(Debug, Default)]
+pub struct LoadedParser {
+    pub parser: Arc<MediaParser>,
+    pub errors: Vec<String>,
+    custom_modified: Option<SystemTime>,
+}
+
+impl LoadedParser {
+    /// Rules that fail to compile are left out so the rest keep working
+    pub fn new(data: &RecognitionData) -> Self {
+        let (parser, errors) = MediaParser::with_errors(&data.rules);
+        for err in errors.iter() {
+            warn!("could not compile recognition {}", err);
+        }
+        LoadedParser {
+            parser: Arc::new(parser),
+            errors: errors.iter().map(|err| err.to_string()).collect(),
+            custom_modified: None,
+        }
+    }
+
+    /// Swaps in a parser for the merged rules, returning them if it was swapped.
+    /// The last good parser is kept if the custom file can't be read or any rule doesn't compile.
+    pub fn reload(
+        &mut self,
+        mut bundled: RecognitionData,
+        custom: Result<Option<RecognitionData>>,
+    ) -> Option<RecognitionData> {
+        match custom {
+            Ok(Some(custom)) => bundled.merge(custom),
+            Ok(None) => {}
+            Err(err) => {
+                warn!("could not load custom recognition data: {}", err);
+                self.errors = vec![err.to_string()];
+                return None;
+            }
+        }
+
+        let (parser, errors) = MediaParser::with_errors(&bundled.rules);
+        if errors.is_empty() {
+            self.parser = Arc::new(parser);
+            self.errors.clear();
+            Some(bundled)
+        } else {
+            for err in errors.iter() {
+                warn!("could not compile recognition {}", err);
+            }
+            self.errors = errors.iter().map(|err| err.to_string()).collect();
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUSTOM: &str = r#"{
+        "version": 2,
+        "rules": [{
+            "id": "custom-site",
+            "name": "Custom Site",
+            "media_type": "ANIME",
+            "regex": "^(?P<title>.+) ep (?P<episode>\\d+) - Custom Site"
+        }]
+    }"#;
+
+    #[test]
+    fn keeps_last_good_parser() {
+        let bundled = RecognitionData::load().unwrap();
+        let mut loaded = LoadedParser::new(&bundled);
+        assert!(loaded.errors.is_empty());
+
+        let custom = RecognitionData::from_reader(CUSTOM.as_bytes(), "custom-").unwrap();
+        assert!(loaded.reload(bundled.clone(), Ok(Some(custom))).is_some());
+        let media = loaded
+            .parser
+            .parse_title("Show ep 2 - Custom Site")
+            .unwrap();
+        assert_eq!(media.rule.as_deref(), Some("custom-site"));
+
+        let mut broken = RecognitionData::from_reader(CUSTOM.as_bytes(), "custom-").unwrap();
+        broken.rules[0].regex = "(?P<title>".to_string();
+        let good = loaded.parser.clone();
+        assert!(loaded.reload(bundled.clone(), Ok(Some(broken))).is_none());
+        assert!(Arc::ptr_eq(&good, &loaded.parser));
+        assert_eq!(loaded.errors.len(), 1);
+        assert!(loaded.errors[0].contains("custom-site"));
+
+        let unreadable = RecognitionData::from_reader("{".as_bytes(), "custom-").map(Some);
+        assert!(loaded.reload(bundled, unreadable).is_none());
+        assert!(Arc::ptr_eq(&good, &loaded.parser));
+    }
+}
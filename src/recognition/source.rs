@@ -0,0 +1,321 @@
+use super::{get_window_titles, Media, Playback};
+use async_trait::async_trait;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, fmt::Debug};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    WindowTitles,
+    Mpris,
+    Mpv,
+    Fixture,
+}
+
+/// Something a source saw that might be media
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hint {
+    /// Text that still has to go through the media parser, like a window title or file name
+    Text(String),
+    /// Media the source already knows the details of
+    Media(Media),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub hint: Hint,
+    pub source: SourceKind,
+    /// Candidates with a higher priority are parsed first
+    pub priority: i32,
+    pub playback: Option<Playback>,
+}
+
+impl Candidate {
+    pub fn text(text: String, source: SourceKind, priority: i32) -> Self {
+        Candidate {
+            hint: Hint::Text(text),
+            source,
+            priority,
+            playback: None,
+        }
+    }
+
+    pub fn with_playback(self, playback: Option<Playback>) -> Self {
+        Candidate { playback, ..self }
+    }
+}
+
+#[async_trait]
+pub trait MediaSource: Debug + Send + Sync {
+    fn kind(&self) -> SourceKind;
+
+    async fn candidates(&self) -> Vec<Candidate>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceSettings {
+    pub kind: SourceKind,
+    #[serde(default = "SourceSettings::enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub priority: i32,
+    /// Titles reported by a fixture source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub titles: Vec<String>,
+}
+
+impl SourceSettings {
+    fn enabled_default() -> bool {
+        true
+    }
+
+    /// Players go first since they can report playback position
+    pub fn defaults() -> Vec<Self> {
+        let source = |kind, priority| SourceSettings {
+            kind,
+            enabled: true,
+            priority,
+            titles: Vec::new(),
+        };
+        vec![
+            source(SourceKind::Mpv, 30),
+            source(SourceKind::Mpris, 20),
+            source(SourceKind::WindowTitles, 10),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MediaSources {
+    sources: Vec<(Box<dyn MediaSource>, i32)>,
+}
+
+impl MediaSources {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    pub fn from_settings(settings: &[SourceSettings], mpv_sockets: &[String]) -> Self {
+        let mut sources = MediaSources::default();
+        for source in settings.iter().filter(|source| source.enabled) {
+            let media_source: Box<dyn MediaSource> = match source.kind {
+                SourceKind::WindowTitles => Box::new(WindowTitleSource),
+                #[cfg(all(target_os = "linux", feature = "mpris"))]
+                SourceKind::Mpris => Box::new(MprisSource),
+                #[cfg(unix)]
+                SourceKind::Mpv => Box::new(MpvSource {
+                    sockets: mpv_sockets.to_vec(),
+                }),
+                SourceKind::Fixture => Box::new(FixtureSource::new(source.titles.clone())),
+                #[allow(unreachable_patterns)]
+                kind => {
+                    debug!("{:?} media source is not supported on this platform", kind);
+                    continue;
+                }
+            };
+            sources.push(media_source, source.priority);
+        }
+        sources
+    }
+
+    pub fn push(&mut self, source: Box<dyn MediaSource>, priority: i32) {
+        self.sources.push((source, priority));
+    }
+
+    /// Every candidate from every source, highest priority first
+    pub async fn candidates(&self) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for (source, priority) in self.sources.iter() {
+            for candidate in source.candidates().await {
+                candidates.push(Candidate {
+                    priority: *priority + candidate.priority,
+                    ..candidate
+                });
+            }
+        }
+        candidates.sort_by_key(|candidate| Reverse(candidate.priority));
+        candidates
+    }
+}
+
+#[derive(Debug)]
+pub struct WindowTitleSource;
+
+#[async_trait]
+impl MediaSource for WindowTitleSource {
+    fn kind(&self) -> SourceKind {
+        SourceKind::WindowTitles
+    }
+
+    async fn candidates(&self) -> Vec<Candidate> {
+        get_window_titles()
+            .into_iter()
+            .map(|title| Candidate::text(title, self.kind(), 0))
+            .collect()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "mpris"))]
+#[derive(Debug)]
+pub struct MprisSource;
+
+#[cfg(all(target_os = "linux", feature = "mpris"))]
+#[async_trait]
+impl MediaSource for MprisSource {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Mpris
+    }
+
+    async fn candidates(&self) -> Vec<Candidate> {
+        // D-Bus calls block for up to the timeout per player, so they run off the executor
+        let players = tokio::task::spawn_blocking(super::get_mpris_players)
+            .await
+            .unwrap_or_else(|err| {
+                log::warn!("could not read mpris players: {}", err);
+                Vec::new()
+            });
+        let mut candidates = Vec::new();
+        for player in players {
+            let playback = player.playback();
+            candidates.extend(
+                player.candidates().into_iter().map(|text| {
+                    Candidate::text(text, self.kind(), 0).with_playback(Some(playback))
+                }),
+            );
+        }
+        candidates
+    }
+}
+
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct MpvSource {
+    pub sockets: Vec<String>,
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl MediaSource for MpvSource {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Mpv
+    }
+
+    async fn candidates(&self) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for player in super::get_mpv_players(&self.sockets).await {
+            let playback = player.playback();
+            candidates.extend(
+                player.candidates().into_iter().map(|text| {
+                    Candidate::text(text, self.kind(), 0).with_playback(Some(playback))
+                }),
+            );
+        }
+        candidates
+    }
+}
+
+/// Reports a fixed set of candidates, for tests and running without any players
+#[derive(Debug, Default)]
+pub struct FixtureSource {
+    pub candidates: Vec<Candidate>,
+}
+
+impl FixtureSource {
+    pub fn new(titles: Vec<String>) -> Self {
+        FixtureSource {
+            candidates: titles
+                .into_iter()
+                .map(|title| Candidate::text(title, SourceKind::Fixture, 0))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl MediaSource for FixtureSource {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Fixture
+    }
+
+    async fn candidates(&self) -> Vec<Candidate> {
+        self.candidates.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        anilist::MediaType,
+        recognition::{MediaParser, FILENAME_RULE},
+        settings::RecognitionData,
+    };
+
+    fn fixture(titles: &[&str]) -> Box<dyn MediaSource> {
+        Box::new(FixtureSource::new(
+            titles.iter().map(|title| title.to_string()).collect(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn candidates_are_ordered_by_priority() {
+        let mut sources = MediaSources::default();
+        sources.push(fixture(&["low"]), 1);
+        sources.push(fixture(&["high", "high second"]), 5);
+        let hints: Vec<Hint> = sources
+            .candidates()
+            .await
+            .into_iter()
+            .map(|candidate| candidate.hint)
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                Hint::Text("high".to_string()),
+                Hint::Text("high second".to_string()),
+                Hint::Text("low".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn detects_media_from_fixture() {
+        let parser = MediaParser::new(&RecognitionData::load().unwrap().rules).unwrap();
+        let playback = Playback {
+            percent: Some(80.0),
+            paused: false,
+        };
+        let mut sources = MediaSources::default();
+        sources.push(
+            fixture(&["Terminal", "Stream Episode 3 of Made in Abyss on HIDIVE"]),
+            0,
+        );
+        sources.push(
+            Box::new(FixtureSource {
+                candidates: vec![Candidate::text(
+                    "/videos/[Group] Other Show - 07 [1080p].mkv".to_string(),
+                    SourceKind::Fixture,
+                    0,
+                )
+                .with_playback(Some(playback))],
+            }),
+            10,
+        );
+
+        let media = parser.detect_from(&sources).await.unwrap();
+        assert_eq!(media.title, "Other Show");
+        assert_eq!(media.media_type, MediaType::Anime);
+        assert_eq!(media.progress, Some(7.0));
+        assert_eq!(media.playback, Some(playback));
+        assert_eq!(media.rule.as_deref(), Some(FILENAME_RULE));
+
+        sources = MediaSources::default();
+        sources.push(
+            fixture(&["Terminal", "Stream Episode 3 of Made in Abyss on HIDIVE"]),
+            0,
+        );
+        let media = parser.detect_from(&sources).await.unwrap();
+        assert_eq!(media.title, "Made in Abyss");
+        assert_eq!(media.progress, Some(3.0));
+        assert_eq!(media.rule.as_deref(), Some("hidive"));
+        assert_eq!(media.playback, None);
+    }
+}
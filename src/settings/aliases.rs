@@ -0,0 +1,145 @@
+use super::file_path;
+use crate::anilist::MediaType;
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    default::Default,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TitleAlias {
+    /// The `NormalizedTitle::alias_key` of the recognized title
+    pub title: String,
+    pub media_type: MediaType,
+    pub media_id: i32,
+}
+
+/// Media the user said recognized titles are, used instead of searching for them
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TitleAliases {
+    aliases: Vec<TitleAlias>,
+}
+
+impl TitleAliases {
+    const FILE: &'static str = "title_aliases.json";
+
+    /// The saved aliases, a broken file is only warned about so the rest of the settings load
+    pub fn load() -> Self {
+        let path = match file_path(Self::FILE) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("could not get title aliases path: {}", err);
+                return Self::default();
+            }
+        };
+        match File::open(&path) {
+            Ok(file) => match serde_json::from_reader(BufReader::new(file)) {
+                Ok(aliases) => aliases,
+                Err(err) => {
+                    warn!("could not read {:?}: {}", path, err);
+                    Self::default()
+                }
+            },
+            Err(err) => {
+                warn!("could not open {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = file_path(Self::FILE)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, &self)?;
+        Ok(())
+    }
+
+    pub fn get(&self, title: &str, media_type: MediaType) -> Option<i32> {
+        self.aliases
+            .iter()
+            .find(|alias| alias.title == title && alias.media_type == media_type)
+            .map(|alias| alias.media_id)
+    }
+
+    /// Maps the title to the media, replacing what it was mapped to before
+    pub fn set(&mut self, title: String, media_type: MediaType, media_id: i32) {
+        match self
+            .aliases
+            .iter_mut()
+            .find(|alias| alias.title == title && alias.media_type == media_type)
+        {
+            Some(alias) => alias.media_id = media_id,
+            None => self.aliases.push(TitleAlias {
+                title,
+                media_type,
+                media_id,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, title: &str, media_type: MediaType) -> Option<TitleAlias> {
+        let index = self
+            .aliases
+            .iter()
+            .position(|alias| alias.title == title && alias.media_type == media_type)?;
+        Some(self.aliases.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognition::Normalizer;
+
+    #[test]
+    fn aliases_are_keyed_by_title_and_type() {
+        let mut aliases = TitleAliases::default();
+        aliases.set("show name".to_string(), MediaType::Anime, 1);
+        aliases.set("show name".to_string(), MediaType::Manga, 2);
+        aliases.set("show name".to_string(), MediaType::Anime, 3);
+        assert_eq!(aliases.get("show name", MediaType::Anime), Some(3));
+        assert_eq!(aliases.get("show name", MediaType::Manga), Some(2));
+        assert_eq!(aliases.get("other", MediaType::Anime), None);
+
+        let json = serde_json::to_string(&aliases).unwrap();
+        let loaded: TitleAliases = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, aliases);
+
+        assert!(aliases.remove("show name", MediaType::Manga).is_some());
+        assert_eq!(aliases.get("show name", MediaType::Manga), None);
+    }
+
+    #[test]
+    fn aliases_keep_the_season() {
+        let normalizer = Normalizer::default();
+        let key = |title| normalizer.normalize(title).alias_key();
+        let mut aliases = TitleAliases::default();
+        aliases.set(key("Show Name Season 2"), MediaType::Anime, 2);
+        assert_eq!(
+            aliases.get(&key("Show Name 2nd Season"), MediaType::Anime),
+            Some(2)
+        );
+        assert_eq!(aliases.get(&key("Show Name"), MediaType::Anime), None);
+        assert_eq!(
+            aliases.get(&key("Show Name Season 1"), MediaType::Anime),
+            None
+        );
+        assert_eq!(
+            aliases.get(&key("Show Name Season 3"), MediaType::Anime),
+            None
+        );
+        assert_eq!(
+            aliases.get(&key("Show Name (Movie)"), MediaType::Anime),
+            None
+        );
+        assert_eq!(key("Show Name Season 1"), key("Show Name"));
+    }
+}
@@ -11,6 +11,9 @@ use std::{
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AniListData {
     token: Option<String>,
+    /// Used over the saved token for this run only, it's never written to the file
+    #[serde(skip)]
+    session_token: Option<String>,
 }
 
 impl AniListData {
@@ -33,17 +36,30 @@ impl AniListData {
     }
 
     pub fn save_token(&mut self, tok: &str) {
+        self.session_token = None;
         self.token = Some(tok.to_string())
     }
 
+    /// Uses the token until the app closes without replacing the saved one, for replayed demos
+    pub fn use_session_token(&mut self, tok: &str) {
+        self.session_token = Some(tok.to_string())
+    }
+
+    /// Logging out of a session token leaves the saved token for the next run
     pub fn forget_token(&mut self) -> Result<()> {
+        let session = self.session_token.take().is_some();
         self.token = None;
-        self.save()?;
+        if !session {
+            self.save()?;
+        }
         Ok(())
     }
 
     pub fn token(&self) -> &Option<String> {
-        &self.token
+        match self.session_token {
+            Some(_) => &self.session_token,
+            None => &self.token,
+        }
     }
 
     pub fn save(&self) -> Result<()> {
@@ -61,6 +77,26 @@ impl AniListData {
 
 impl Default for AniListData {
     fn default() -> Self {
-        AniListData { token: None }
+        AniListData {
+            token: None,
+            session_token: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_tokens_are_not_saved() {
+        let mut data: AniListData = serde_json::from_str(r#"{ "token": "saved" }"#).unwrap();
+        data.use_session_token("replay");
+        assert_eq!(data.token().as_deref(), Some("replay"));
+        let written = serde_json::to_string(&data).unwrap();
+        assert!(!written.contains("replay") && written.contains("saved"));
+
+        data.forget_token().unwrap();
+        assert_eq!(data.token(), &None);
     }
 }
@@ -0,0 +1,128 @@
+use super::get_settings;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// Where requests to AniList and the login go, changeable to use a mock server or another
+/// token exchange
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub graphql: String,
+    /// The page the user logs in and authorizes the app on
+    pub authorize: String,
+    /// Where the code from the login is exchanged for a token
+    pub token: String,
+    pub client_id: String,
+    /// Where the login redirects to, the app listens on its host and port
+    pub redirect_uri: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            graphql: "https://graphql.anilist.co".to_string(),
+            authorize: "https://anilist.co/api/v2/oauth/authorize".to_string(),
+            token: "https://auth.fubuki.dev/oauth/token".to_string(),
+            client_id: "2355".to_string(),
+            redirect_uri: "http://localhost:8080/callback".to_string(),
+        }
+    }
+}
+
+/// Endpoints given on the command line or in the environment, used over the settings file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EndpointOverrides {
+    pub graphql: Option<String>,
+    pub authorize: Option<String>,
+    pub token: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+}
+
+static OVERRIDES: OnceCell<EndpointOverrides> = OnceCell::new();
+
+impl EndpointOverrides {
+    /// The environment variable of each override
+    pub const VARS: [&'static str; 5] = [
+        "FUBUKI_GRAPHQL_URL",
+        "FUBUKI_AUTHORIZE_URL",
+        "FUBUKI_TOKEN_URL",
+        "FUBUKI_CLIENT_ID",
+        "FUBUKI_REDIRECT_URI",
+    ];
+
+    pub fn from_env() -> Self {
+        Self::from_vars(|var| std::env::var(var).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(lookup: F) -> Self {
+        let var = |name| lookup(name).filter(|value: &String| !value.is_empty());
+        EndpointOverrides {
+            graphql: var(Self::VARS[0]),
+            authorize: var(Self::VARS[1]),
+            token: var(Self::VARS[2]),
+            client_id: var(Self::VARS[3]),
+            redirect_uri: var(Self::VARS[4]),
+        }
+    }
+
+    /// These overrides, falling back to `other` where they have none
+    pub fn or(self, other: Self) -> Self {
+        EndpointOverrides {
+            graphql: self.graphql.or(other.graphql),
+            authorize: self.authorize.or(other.authorize),
+            token: self.token.or(other.token),
+            client_id: self.client_id.or(other.client_id),
+            redirect_uri: self.redirect_uri.or(other.redirect_uri),
+        }
+    }
+
+    pub fn apply(&self, endpoints: &Endpoints) -> Endpoints {
+        let pick = |value: &Option<String>, default: &String| {
+            value.clone().unwrap_or_else(|| default.clone())
+        };
+        Endpoints {
+            graphql: pick(&self.graphql, &endpoints.graphql),
+            authorize: pick(&self.authorize, &endpoints.authorize),
+            token: pick(&self.token, &endpoints.token),
+            client_id: pick(&self.client_id, &endpoints.client_id),
+            redirect_uri: pick(&self.redirect_uri, &endpoints.redirect_uri),
+        }
+    }
+}
+
+/// Uses the command line overrides, then the environment, over the settings for the rest of the
+/// run. Only the first call has an effect.
+pub fn set_endpoint_overrides(cli: EndpointOverrides) {
+    let _ = OVERRIDES.set(cli.or(EndpointOverrides::from_env()));
+}
+
+/// The endpoints in use, don't call this while holding the settings lock
+pub fn endpoints() -> Endpoints {
+    let overrides = OVERRIDES.get_or_init(EndpointOverrides::from_env);
+    let settings = get_settings().read().unwrap();
+    overrides.apply(&settings.endpoints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_take_precedence() {
+        let env = EndpointOverrides::from_vars(|var| match var {
+            "FUBUKI_GRAPHQL_URL" => Some("http://localhost:4000/env".to_string()),
+            "FUBUKI_CLIENT_ID" => Some("1".to_string()),
+            "FUBUKI_TOKEN_URL" => Some(String::new()),
+            _ => None,
+        });
+        let cli = EndpointOverrides {
+            graphql: Some("http://localhost:4000/cli".to_string()),
+            ..EndpointOverrides::default()
+        };
+        let endpoints = cli.or(env).apply(&Endpoints::default());
+        assert_eq!(endpoints.graphql, "http://localhost:4000/cli");
+        assert_eq!(endpoints.client_id, "1");
+        assert_eq!(endpoints.token, Endpoints::default().token);
+    }
+}
@@ -1,9 +1,13 @@
+mod aliases;
 mod anilist;
+mod endpoints;
 mod recognition;
 pub mod settings;
 
+pub use aliases::{TitleAlias, TitleAliases};
 pub use anilist::AniListData;
-pub use recognition::RecognitionData;
+pub use endpoints::{endpoints, set_endpoint_overrides, EndpointOverrides, Endpoints};
+pub use recognition::{ExpectedMatch, RecognitionData, RecognitionRule, RuleExample, TitleCleanup};
 pub use settings::{Settings, SETTINGS};
 
 use anyhow::Result;
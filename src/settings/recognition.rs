@@ -1,80 +1,266 @@
 use super::file_path;
-use crate::resources::Resources;
-use anyhow::Result;
-use log::warn;
+use crate::{anilist::MediaType, resources::Resources};
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::{default::Default, fs::File, io::BufReader};
+use std::{
+    default::Default,
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::PathBuf,
+};
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TitleCleanup {
+    pub pattern: String,
+    #[serde(default)]
+    pub replace: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExpectedMatch {
+    pub title: String,
+    #[serde(default)]
+    pub episode: Option<f64>,
+    #[serde(default)]
+    pub episode_end: Option<f64>,
+    #[serde(default)]
+    pub chapter: Option<f64>,
+    #[serde(default)]
+    pub chapter_end: Option<f64>,
+    #[serde(default)]
+    pub volume: Option<f64>,
+    #[serde(default)]
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleExample {
+    pub title: String,
+    /// What the rule should capture from the title, or None if the rule must not match it
+    #[serde(default)]
+    pub expected: Option<ExpectedMatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecognitionRule {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub site: Option<String>,
+    pub media_type: MediaType,
+    /// Rules with a higher priority are tried first
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "RecognitionRule::enabled_default")]
+    pub enabled: bool,
+    pub regex: String,
+    /// Substitutions applied in order to the captured title
+    #[serde(default)]
+    pub cleanup: Vec<TitleCleanup>,
+    #[serde(default)]
+    pub examples: Vec<RuleExample>,
+    /// The id of a bundled rule this custom rule is used instead of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+}
+
+impl RecognitionRule {
+    fn enabled_default() -> bool {
+        true
+    }
+
+    fn from_regex(id: String, media_type: MediaType, regex: String) -> Self {
+        RecognitionRule {
+            name: id.clone(),
+            id,
+            site: None,
+            media_type,
+            priority: 0,
+            enabled: true,
+            regex,
+            cleanup: Vec::new(),
+            examples: Vec::new(),
+            replaces: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RecognitionData {
-    pub anime: Vec<String>,
-    pub manga: Vec<String>,
+    pub version: u32,
+    pub rules: Vec<RecognitionRule>,
 }
 
-impl<'a> RecognitionData {
+/// Only the version of a recognition file, read first to pick the layout to read it as
+#[derive(Deserialize)]
+struct FileVersion {
+    #[serde(default)]
+    version: Option<u32>,
+}
+
+/// The layout before rules had metadata, two lists of regexes
+#[derive(Deserialize)]
+struct LegacyFile {
+    anime: Vec<String>,
+    manga: Vec<String>,
+}
+
+impl LegacyFile {
+    fn into_data(self) -> RecognitionData {
+        let anime = self.anime.into_iter().enumerate().map(|(i, regex)| {
+            RecognitionRule::from_regex(format!("anime-{}", i), MediaType::Anime, regex)
+        });
+        let manga = self.manga.into_iter().enumerate().map(|(i, regex)| {
+            RecognitionRule::from_regex(format!("manga-{}", i), MediaType::Manga, regex)
+        });
+        RecognitionData {
+            version: RecognitionData::VERSION,
+            rules: anime.chain(manga).collect(),
+        }
+    }
+}
+
+impl Default for RecognitionData {
+    fn default() -> Self {
+        RecognitionData {
+            version: Self::VERSION,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl RecognitionData {
+    pub const VERSION: u32 = 2;
     const CUSTOM_RECOGNITION: &'static str = "recognition_custom.json";
 
     pub fn load() -> Result<Self> {
         match Resources::get("recognition.json") {
-            Some(file) => {
-                let rdr = BufReader::new(&*file);
-                let r: RecognitionData = serde_json::from_reader(rdr)?;
-                Ok(r)
-            }
+            Some(file) => Self::from_reader(BufReader::new(&*file), ""),
             None => {
                 warn!("could not open default recognition file");
-                let default = Self::default();
-                // default.save()?;
-                Ok(default)
+                Ok(Self::default())
             }
         }
     }
 
     pub fn load_with_custom() -> Result<Self> {
         let mut r = Self::load()?;
-        let path = file_path(Self::CUSTOM_RECOGNITION)?;
-        match File::open(&path) {
-            Ok(file) => {
-                let rdr = BufReader::new(file);
-                let custom = serde_json::from_reader::<BufReader<File>, RecognitionData>(rdr);
-                match custom {
-                    Ok(custom) => {
-                        r.anime = itertools::chain(r.anime, custom.anime).collect();
-                        r.manga = itertools::chain(r.manga, custom.manga).collect();
-                    }
-                    Err(err) => {
-                        warn!("error deserializing custom recognition data: {}", err);
-                    }
-                }
-            }
+        match Self::load_custom() {
+            Ok(Some(custom)) => r.merge(custom),
+            Ok(None) => {}
             Err(err) => {
-                warn!("could not open custom recognition file {:?}: {}", path, err);
+                warn!("error loading custom recognition data: {}", err);
             }
         }
         Ok(r)
     }
 
-    // pub fn save(&self) -> Result<()> {
-    //     let path = Path::new(Self::PATH);
-    //     let file = OpenOptions::new()
-    //         .write(true)
-    //         .truncate(true)
-    //         .open(path)?;
-    //     let writer = BufWriter::new(file);
-    //     serde_json::to_writer_pretty(writer, &self)?;
-    //     Ok(())
-    // }
+    pub fn custom_path() -> Result<PathBuf> {
+        file_path(Self::CUSTOM_RECOGNITION)
+    }
+
+    /// The user's own rules, or None if they haven't made the file
+    pub fn load_custom() -> Result<Option<Self>> {
+        let path = Self::custom_path()?;
+        match File::open(&path) {
+            Ok(file) => Ok(Some(Self::from_reader(BufReader::new(file), "custom-")?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                debug!("no custom recognition file at {:?}", path);
+                Ok(None)
+            }
+            Err(err) => Err(anyhow!("could not open {:?}: {}", path, err)),
+        }
+    }
+
+    /// Reads either layout, every id gets the prefix so custom rules can't take a bundled id.
+    /// Legacy rules get ids made from their media type and index.
+    pub fn from_reader<R: std::io::Read>(mut rdr: R, id_prefix: &str) -> Result<Self> {
+        let mut json = String::new();
+        rdr.read_to_string(&mut json)?;
+        let mut data = match serde_json::from_str::<FileVersion>(&json)?.version {
+            None => serde_json::from_str::<LegacyFile>(&json)?.into_data(),
+            Some(Self::VERSION) => serde_json::from_str::<RecognitionData>(&json)?,
+            Some(version) => {
+                return Err(anyhow!(
+                    "unsupported recognition file version {}, expected {}",
+                    version,
+                    Self::VERSION
+                ))
+            }
+        };
+        for rule in data.rules.iter_mut() {
+            if !rule.id.starts_with(id_prefix) {
+                rule.id = format!("{}{}", id_prefix, rule.id);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Adds the other rules, a rule replaces the one with the same id or the one it `replaces`
+    pub fn merge(&mut self, other: RecognitionData) {
+        for rule in other.rules {
+            let replaced = rule.replaces.as_ref().unwrap_or(&rule.id);
+            match self.rules.iter_mut().find(|r| &r.id == replaced) {
+                Some(existing) => *existing = rule,
+                None => {
+                    if let Some(replaces) = &rule.replaces {
+                        warn!(
+                            "recognition rule {} replaces unknown rule {}",
+                            rule.id, replaces
+                        );
+                    }
+                    self.rules.push(rule)
+                }
+            }
+        }
+    }
 }
 
-// impl Default for RecognitionData {
-//     fn default() -> Self {
-//         RecognitionData {
-//             anime: vec![
-//                 "^(?P<title>.+) Episode (?P<episode>\\d+),.+?- Watch on Crunchyroll".to_string(),
-//             ],
-//             manga: vec![
-//                 "^(?P<title>.+) - (Vol[.] (?P<volume>\\d+) )?(Ch[.] (?P<chapter>(\\d+[.])?\\d+) )?(?P<oneshot>Oneshot)?.*?- MangaDex".to_string(),
-//             ],
-//         }
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_legacy_layout() {
+        let legacy = r#"{ "anime": ["^(?P<title>.+) - (?P<episode>\\d+)$"], "manga": [] }"#;
+        let data = RecognitionData::from_reader(legacy.as_bytes(), "custom-").unwrap();
+        assert_eq!(data.version, RecognitionData::VERSION);
+        assert_eq!(data.rules.len(), 1);
+        assert_eq!(data.rules[0].id, "custom-anime-0");
+        assert_eq!(data.rules[0].media_type, MediaType::Anime);
+        assert!(data.rules[0].enabled);
+    }
+
+    #[test]
+    fn custom_rules_replace_explicitly() {
+        let mut data = RecognitionData::load().unwrap();
+        let count = data.rules.len();
+        let bundled = data.rules[0].id.clone();
+        let custom = format!(
+            r#"{{ "version": 2, "rules": [
+                {{ "id": "{0}", "name": "Same id", "media_type": "ANIME", "regex": "^a$" }},
+                {{ "id": "off", "name": "Off", "media_type": "ANIME", "regex": "^b$",
+                   "enabled": false, "replaces": "{0}" }}
+            ] }}"#,
+            bundled
+        );
+        data.merge(RecognitionData::from_reader(custom.as_bytes(), "custom-").unwrap());
+        assert_eq!(data.rules.len(), count + 1);
+        assert_eq!(data.rules[0].id, "custom-off");
+        assert!(!data.rules[0].enabled);
+        assert_eq!(data.rules[count].id, format!("custom-{}", bundled));
+    }
+
+    #[test]
+    fn points_at_bad_fields() {
+        let typo =
+            r#"{ "version": 2, "rules": [{ "id": "a", "name": "A", "media_typ": "ANIME" }] }"#;
+        let err = RecognitionData::from_reader(typo.as_bytes(), "custom-").unwrap_err();
+        assert!(err.to_string().contains("media_type"), "{}", err);
+
+        let future = r#"{ "version": 3, "rules": [] }"#;
+        let err = RecognitionData::from_reader(future.as_bytes(), "custom-").unwrap_err();
+        assert!(err.to_string().contains("version 3"), "{}", err);
+    }
+}
@@ -1,5 +1,10 @@
 use super::file_path;
-use super::{AniListData, RecognitionData};
+use super::{AniListData, Endpoints, RecognitionData, TitleAliases};
+use crate::anilist::{
+    AuthFlow, AutoAddSettings, MatchSettings, Relations, RepeatMode, StatusRules, UpdateMode,
+    WatchPolicies,
+};
+use crate::recognition::{FractionalEpisodes, NormalizeSettings, SourceSettings};
 use anyhow::Result;
 use log::warn;
 use once_cell::sync::Lazy;
@@ -19,12 +24,46 @@ pub static SETTINGS: Lazy<RwLock<Settings>> = Lazy::new(|| match Settings::load(
     }
 });
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     #[serde(skip)]
     pub anilist: AniListData,
     #[serde(skip)]
     pub recognition: RecognitionData,
+    #[serde(skip)]
+    pub aliases: TitleAliases,
+    /// Episode redirections between seasons, see `anilist::Relations`
+    #[serde(skip)]
+    pub relations: Relations,
+    /// Seconds a queued update waits before it's sent
     pub update_delay: u64,
+    /// Whether detected updates wait for the user to accept them
+    pub update_mode: UpdateMode,
+    /// Where media is detected from, see `recognition::MediaSources`
+    pub sources: Vec<SourceSettings>,
+    /// Paths to mpv `--input-ipc-server` sockets to query for playback
+    pub mpv_sockets: Vec<String>,
+    /// What has to happen while media is detected before its update is sent
+    pub watch_policy: WatchPolicies,
+    /// The playback percentage before `watch_policy`, only read to carry it over
+    #[serde(skip_serializing)]
+    watched_percentage: Option<f64>,
+    /// How episodes like "12.5" are counted in the list
+    pub fractional_episodes: FractionalEpisodes,
+    /// Whether watching a completed media from the start switches it to Repeating
+    pub repeat_mode: RepeatMode,
+    /// When list entries are started and completed as progress is made
+    pub status_rules: StatusRules,
+    /// Steps applied to recognized titles before they are searched for and matched
+    pub normalize: NormalizeSettings,
+    /// How search results are scored and when the user is asked to pick one
+    pub matching: MatchSettings,
+    /// Whether media that isn't in the user's list gets added to it
+    pub auto_add: AutoAddSettings,
+    /// The AniList API and login servers, see `settings::endpoints` for the overrides
+    pub endpoints: Endpoints,
+    /// How the user logs in to AniList
+    pub auth_flow: AuthFlow,
 }
 
 impl Settings {
@@ -45,16 +84,28 @@ impl Settings {
             }
         };
         let settings = match settings {
-            Ok(settings) => settings,
+            Ok(settings) => settings.migrate(),
             Err(_) => Self::default(),
         };
         Ok(Settings {
             anilist: AniListData::load()?,
             recognition: RecognitionData::load_with_custom()?,
+            aliases: TitleAliases::load(),
+            relations: Relations::load()?,
             ..settings
         })
     }
 
+    /// Moves settings from older files to where they are now, and drops values that can't work
+    fn migrate(mut self) -> Self {
+        if let Some(percentage) = self.watched_percentage.take() {
+            self.watch_policy.anime.min_playback_percentage = percentage;
+            self.watch_policy.manga.min_playback_percentage = percentage;
+        }
+        self.matching = self.matching.validated();
+        self
+    }
+
     pub fn save(&self) -> Result<()> {
         let path = file_path(Self::FILE)?;
         let file = OpenOptions::new()
@@ -76,7 +127,41 @@ impl Default for Settings {
         Settings {
             anilist: AniListData::default(),
             recognition: RecognitionData::default(),
+            aliases: TitleAliases::default(),
+            relations: Relations::default(),
             update_delay: 5,
+            update_mode: UpdateMode::default(),
+            sources: SourceSettings::defaults(),
+            mpv_sockets: Vec::new(),
+            watch_policy: WatchPolicies::default(),
+            watched_percentage: None,
+            fractional_episodes: FractionalEpisodes::default(),
+            repeat_mode: RepeatMode::default(),
+            status_rules: StatusRules::default(),
+            normalize: NormalizeSettings::default(),
+            matching: MatchSettings::default(),
+            auto_add: AutoAddSettings::default(),
+            endpoints: Endpoints::default(),
+            auth_flow: AuthFlow::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_over_watched_percentage() {
+        let old: Settings = serde_json::from_str(r#"{ "watched_percentage": 90.0 }"#).unwrap();
+        let settings = old.migrate();
+        assert_eq!(settings.watch_policy.anime.min_playback_percentage, 90.0);
+        assert_eq!(settings.watch_policy.manga.min_playback_percentage, 90.0);
+        assert_eq!(
+            settings.watch_policy.anime.min_detection_secs,
+            WatchPolicies::default().anime.min_detection_secs
+        );
+        let saved = serde_json::to_value(&settings).unwrap();
+        assert!(saved.get("watched_percentage").is_none());
+    }
+}
@@ -17,12 +17,32 @@ impl Event for CurrentMediaPress {
             app.nav.anime_selected = false;
             app.nav.manga_selected = false;
             app.nav.media_selected = true;
+            app.nav.updates_selected = false;
             app.page.change_page(super::Page::CurrentMedia);
         }
         None
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct UpdatesPress {
+    selected: bool,
+}
+
+impl Event for UpdatesPress {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        if !self.selected {
+            app.nav.settings_selected = false;
+            app.nav.anime_selected = false;
+            app.nav.manga_selected = false;
+            app.nav.media_selected = false;
+            app.nav.updates_selected = true;
+            app.page.change_page(super::Page::Updates);
+        }
+        None
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SettingsPress {
     selected: bool,
@@ -35,6 +55,7 @@ impl Event for SettingsPress {
             app.nav.anime_selected = false;
             app.nav.manga_selected = false;
             app.nav.media_selected = false;
+            app.nav.updates_selected = false;
             app.page.change_page(super::Page::Settings);
         }
         None
@@ -53,6 +74,7 @@ impl Event for AnimeListPress {
             app.nav.anime_selected = true;
             app.nav.manga_selected = false;
             app.nav.media_selected = false;
+            app.nav.updates_selected = false;
             app.page.change_page(super::Page::Anime);
         }
         None
@@ -71,6 +93,7 @@ impl Event for MangaListPress {
             app.nav.anime_selected = false;
             app.nav.manga_selected = true;
             app.nav.media_selected = false;
+            app.nav.updates_selected = false;
             app.page.change_page(super::Page::Manga);
         }
         None
@@ -82,9 +105,11 @@ pub struct Nav {
     anime_state: button::State,
     manga_state: button::State,
     media_state: button::State,
+    updates_state: button::State,
     settings_state: button::State,
     refresh_state: button::State,
     media_selected: bool,
+    updates_selected: bool,
     settings_selected: bool,
     anime_selected: bool,
     manga_selected: bool,
@@ -152,6 +177,15 @@ impl Nav {
             }
             .into(),
         );
+        let updates = Self::nav_button(
+            &mut self.updates_state,
+            "Updates",
+            self.updates_selected,
+            UpdatesPress {
+                selected: self.updates_selected,
+            }
+            .into(),
+        );
         let settings = Self::nav_button(
             &mut self.settings_state,
             "Settings",
@@ -181,6 +215,7 @@ impl Nav {
             .push(anime)
             .push(manga)
             .push(media)
+            .push(updates)
             .push(settings)
             .push(right_spacer);
 
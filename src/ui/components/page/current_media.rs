@@ -1,9 +1,10 @@
 use super::PageContainer;
 use crate::{
     anilist,
-    app::{App, Event, Message},
+    app::{forward_message, App, Event, Message},
     recognition,
     resources::Resources,
+    settings,
     ui::style,
 };
 use iced::{
@@ -14,11 +15,36 @@ use log::{debug, warn};
 #[derive(Debug, Clone)]
 pub struct CurrentMediaPage {
     update_cancel_btn_state: button::State,
+    choose_media_btn_state: button::State,
     show_cancel_update: bool,
     current: Option<anilist::MediaList>,
     recognized: Option<recognition::Media>,
     cover: Option<image::Handle>,
     default_cover: image::Handle,
+    /// Search results that were too close to pick between automatically
+    match_choices: Vec<anilist::MatchCandidate>,
+    match_choice_states: Vec<button::State>,
+    match_dismiss_state: button::State,
+    /// How far the detected media is toward its update being sent
+    watch_progress: Option<anilist::WatchProgress>,
+    /// The update starting a rewatch of a completed media, waiting on the user
+    repeat_prompt: Option<anilist::MediaList>,
+    repeat_yes_state: button::State,
+    repeat_no_state: button::State,
+    /// The update with a status change the user has to approve
+    status_prompt: Option<(anilist::MediaList, anilist::StatusChange)>,
+    status_yes_state: button::State,
+    status_no_state: button::State,
+    /// The best match for detected media that isn't in the list, offered to be added
+    add_prompt: Option<anilist::MatchCandidate>,
+    add_yes_state: button::State,
+    add_no_state: button::State,
+    /// The update of the current media while it waits on the user
+    pending_update: Option<anilist::PendingUpdate>,
+    accept_state: button::State,
+    decrement_state: button::State,
+    increment_state: button::State,
+    reject_state: button::State,
 }
 
 impl CurrentMediaPage {
@@ -53,7 +79,7 @@ impl CurrentMediaPage {
                             inner_row.push(Text::new("Could Not Get Title").size(title_size))
                     }
                 }
-                if self.show_cancel_update {
+                if self.show_cancel_update && self.pending_update.is_none() {
                     inner_row = inner_row.push(Text::new("").width(Length::Fill)).push(
                         Button::new(
                             &mut self.update_cancel_btn_state,
@@ -66,11 +92,18 @@ impl CurrentMediaPage {
                         .on_press(CancelListUpdate(current.media_id, false).into()),
                     );
                 }
-                col = col.push(inner_row);
+                col = col.push(inner_row).push(Self::choose_media_button(
+                    &mut self.choose_media_btn_state,
+                    "Wrong Match? Choose Another",
+                ));
                 // current.current_media_string();
                 if let Some(current_detected) = &self.recognized {
                     col = col
                         .push(Text::new(current_detected.current_media_string()).size(text_size));
+                    if let Some(progress) = &self.watch_progress {
+                        col = col
+                            .push(Text::new(Self::watch_progress_text(progress)).size(text_size));
+                    }
                     if let Some(media) = &mut current.media {
                         if let Some(desc) = media.description() {
                             col = col.push(
@@ -83,22 +116,354 @@ impl CurrentMediaPage {
                     }
                 }
             }
-            None => {
-                row = row.push(Text::new("No Media Detected").size(title_size));
-            }
+            None => match &self.recognized {
+                Some(recognized) => {
+                    col = col
+                        .push(
+                            Text::new(format!("No Match For {}", recognized.title))
+                                .size(title_size),
+                        )
+                        .push(Self::choose_media_button(
+                            &mut self.choose_media_btn_state,
+                            "Choose Media",
+                        ));
+                }
+                None => {
+                    row = row.push(Text::new("No Media Detected").size(title_size));
+                }
+            },
+        }
+        if let Some(pending) = &self.pending_update {
+            col = col.push(Self::pending_update(
+                pending,
+                &mut self.accept_state,
+                &mut self.decrement_state,
+                &mut self.increment_state,
+                &mut self.reject_state,
+            ));
+        }
+        if let Some(repeat) = &self.repeat_prompt {
+            col = col.push(Self::repeat_prompt(
+                repeat,
+                &mut self.repeat_yes_state,
+                &mut self.repeat_no_state,
+            ));
+        }
+        if let Some((approved, change)) = &self.status_prompt {
+            col = col.push(Self::status_prompt(
+                approved,
+                *change,
+                &mut self.status_yes_state,
+                &mut self.status_no_state,
+            ));
+        }
+        if let Some(candidate) = &self.add_prompt {
+            let format = match &candidate.format {
+                Some(format) => format!(" ({})", format.str()),
+                None => String::new(),
+            };
+            let question = format!("{}{} isn't in your list, add it?", candidate.title, format);
+            col = col.push(Self::yes_no_prompt(
+                &question,
+                ("Add", &mut self.add_yes_state, AddChosen(true).into()),
+                (&mut self.add_no_state, AddChosen(false).into()),
+            ));
+        }
+        if !self.match_choices.is_empty() {
+            col = col.push(Self::match_prompt(
+                &self.match_choices,
+                &mut self.match_choice_states,
+                &mut self.match_dismiss_state,
+            ));
         }
         PageContainer::container(row.push(col).into()).into()
     }
 
+    fn choose_media_button<'a>(state: &'a mut button::State, label: &str) -> Element<'a, Message> {
+        Button::new(state, Text::new(label).size(14))
+            .padding(12)
+            .style(style::Button::Accent)
+            .on_press(ChooseMedia.into())
+            .into()
+    }
+
+    fn match_prompt<'a>(
+        choices: &[anilist::MatchCandidate],
+        states: &'a mut [button::State],
+        dismiss_state: &'a mut button::State,
+    ) -> Element<'a, Message> {
+        let text_size = 14;
+        let button_padding = 12;
+        let mut col = Column::new()
+            .spacing(6)
+            .push(Text::new("Which of these is it?").size(text_size));
+        for (choice, state) in choices.iter().zip(states.iter_mut()) {
+            let format = match &choice.format {
+                Some(format) => format!(" ({})", format.str()),
+                None => String::new(),
+            };
+            let label = format!(
+                "{}{} - {:.0}%",
+                choice.title,
+                format,
+                choice.confidence * 100.0
+            );
+            col = col.push(
+                Button::new(state, Text::new(label).size(text_size))
+                    .padding(button_padding)
+                    .width(Length::Fill)
+                    .style(style::Button::ListGroup { selected: false })
+                    .on_press(MatchChosen(Some(choice.media_id)).into()),
+            );
+        }
+        col.push(
+            Button::new(
+                dismiss_state,
+                Text::new("None of these")
+                    .size(text_size)
+                    .horizontal_alignment(HorizontalAlignment::Center),
+            )
+            .padding(button_padding)
+            .style(style::Button::Danger)
+            .on_press(MatchChosen(None).into()),
+        )
+        .into()
+    }
+
+    fn repeat_prompt<'a>(
+        repeat: &anilist::MediaList,
+        yes_state: &'a mut button::State,
+        no_state: &'a mut button::State,
+    ) -> Element<'a, Message> {
+        let (question, yes) = match repeat.media.as_ref().and_then(|media| media.media_type) {
+            Some(anilist::MediaType::Manga) => {
+                ("You've completed this before, rereading it?", "Reread")
+            }
+            _ => ("You've completed this before, rewatching it?", "Rewatch"),
+        };
+        Self::yes_no_prompt(
+            question,
+            (yes, yes_state, RepeatChosen(true).into()),
+            (no_state, RepeatChosen(false).into()),
+        )
+    }
+
+    fn status_prompt<'a>(
+        approved: &anilist::MediaList,
+        change: anilist::StatusChange,
+        yes_state: &'a mut button::State,
+        no_state: &'a mut button::State,
+    ) -> Element<'a, Message> {
+        let manga = matches!(
+            approved.media.as_ref().and_then(|media| media.media_type),
+            Some(anilist::MediaType::Manga)
+        );
+        let (question, yes) = match (change, manga) {
+            (anilist::StatusChange::Start, false) => ("Start watching this?", "Watching"),
+            (anilist::StatusChange::Start, true) => ("Start reading this?", "Reading"),
+            (anilist::StatusChange::Complete, false) => ("Finished watching this?", "Completed"),
+            (anilist::StatusChange::Complete, true) => ("Finished reading this?", "Completed"),
+        };
+        Self::yes_no_prompt(
+            question,
+            (yes, yes_state, StatusChangeChosen(true).into()),
+            (no_state, StatusChangeChosen(false).into()),
+        )
+    }
+
+    fn pending_update<'a>(
+        pending: &anilist::PendingUpdate,
+        accept_state: &'a mut button::State,
+        decrement_state: &'a mut button::State,
+        increment_state: &'a mut button::State,
+        reject_state: &'a mut button::State,
+    ) -> Element<'a, Message> {
+        let text_size = 14;
+        let button_padding = 12;
+        let media_id = pending.media.media_id;
+        let button = |state: &'a mut button::State, label: &str, btn_style, msg: Message| {
+            Button::new(
+                state,
+                Text::new(label)
+                    .size(text_size)
+                    .horizontal_alignment(HorizontalAlignment::Center),
+            )
+            .padding(button_padding)
+            .style(btn_style)
+            .on_press(msg)
+        };
+        let heading = if pending.can_accept {
+            "Waiting for you to accept this update:"
+        } else {
+            "Detected update, not sent:"
+        };
+        let mut col = Column::new()
+            .spacing(6)
+            .push(Text::new(heading).size(text_size));
+        if pending.changes.is_empty() {
+            col = col.push(Text::new("Nothing changes").size(text_size));
+        }
+        for change in pending.changes.iter() {
+            col = col.push(
+                Text::new(format!(
+                    "{}: {} -> {}",
+                    change.field, change.before, change.after
+                ))
+                .size(text_size),
+            );
+        }
+        let mut buttons = Row::new().spacing(6);
+        if pending.can_accept {
+            buttons = buttons
+                .push(button(
+                    accept_state,
+                    "Accept",
+                    style::Button::Accent,
+                    AcceptUpdate(media_id).into(),
+                ))
+                .push(button(
+                    decrement_state,
+                    "-",
+                    style::Button::Accent,
+                    EditUpdate(media_id, -1).into(),
+                ))
+                .push(button(
+                    increment_state,
+                    "+",
+                    style::Button::Accent,
+                    EditUpdate(media_id, 1).into(),
+                ));
+        }
+        let reject = if pending.can_accept {
+            "Reject"
+        } else {
+            "Dismiss"
+        };
+        buttons = buttons.push(button(
+            reject_state,
+            reject,
+            style::Button::Danger,
+            CancelListUpdate(media_id, false).into(),
+        ));
+        col.push(buttons).into()
+    }
+
+    fn yes_no_prompt<'a>(
+        question: &str,
+        (yes, yes_state, yes_msg): (&str, &'a mut button::State, Message),
+        (no_state, no_msg): (&'a mut button::State, Message),
+    ) -> Element<'a, Message> {
+        let text_size = 14;
+        let button_padding = 12;
+        let button = |state: &'a mut button::State, label: &str, btn_style, msg: Message| {
+            Button::new(
+                state,
+                Text::new(label)
+                    .size(text_size)
+                    .horizontal_alignment(HorizontalAlignment::Center),
+            )
+            .padding(button_padding)
+            .style(btn_style)
+            .on_press(msg)
+        };
+        Column::new()
+            .spacing(6)
+            .push(Text::new(question).size(text_size))
+            .push(
+                Row::new()
+                    .spacing(6)
+                    .push(button(yes_state, yes, style::Button::Accent, yes_msg))
+                    .push(button(no_state, "No", style::Button::Danger, no_msg)),
+            )
+            .into()
+    }
+
+    fn watch_progress_text(progress: &anilist::WatchProgress) -> String {
+        if progress.is_met() {
+            return "Watched enough, the update will be sent".to_string();
+        }
+        let time = |duration: std::time::Duration| {
+            let secs = duration.as_secs();
+            format!("{}:{:02}", secs / 60, secs % 60)
+        };
+        let mut text = format!(
+            "Updating after {} of {} detected",
+            time(progress.detected),
+            time(progress.required)
+        );
+        if let Some(percent) = progress.percent {
+            text.push_str(&format!(
+                ", {:.0}% of {:.0}% played",
+                percent, progress.required_percent
+            ));
+        }
+        if progress.paused {
+            text.push_str(" (paused)");
+        }
+        text
+    }
+
+    pub fn set_watch_progress(&mut self, progress: Option<anilist::WatchProgress>) {
+        self.watch_progress = progress;
+    }
+
+    pub fn set_match_choices(&mut self, choices: Vec<anilist::MatchCandidate>) {
+        self.match_choice_states = vec![button::State::default(); choices.len()];
+        self.match_choices = choices;
+    }
+
     pub fn show_cancel_button(&mut self, show: bool) {
         self.show_cancel_update = show;
     }
 
+    pub fn set_repeat_prompt(&mut self, repeat: Option<anilist::MediaList>) {
+        self.repeat_prompt = repeat;
+    }
+
+    pub fn take_repeat_prompt(&mut self) -> Option<anilist::MediaList> {
+        self.repeat_prompt.take()
+    }
+
+    pub fn set_pending_update(&mut self, pending: Option<anilist::PendingUpdate>) {
+        self.pending_update = pending;
+    }
+
+    pub fn set_add_prompt(&mut self, candidate: Option<anilist::MatchCandidate>) {
+        self.add_prompt = candidate;
+    }
+
+    pub fn take_add_prompt(&mut self) -> Option<anilist::MatchCandidate> {
+        self.add_prompt.take()
+    }
+
+    pub fn set_status_prompt(
+        &mut self,
+        prompt: Option<(anilist::MediaList, anilist::StatusChange)>,
+    ) {
+        self.status_prompt = prompt;
+    }
+
+    pub fn take_status_prompt(&mut self) -> Option<(anilist::MediaList, anilist::StatusChange)> {
+        self.status_prompt.take()
+    }
+
     pub fn set_current_media(
         &mut self,
         media_list: Option<anilist::MediaList>,
         recognized: Option<recognition::Media>,
     ) {
+        let media_id = media_list.as_ref().map(|media| media.media_id);
+        if self.repeat_prompt.as_ref().map(|repeat| repeat.media_id) != media_id {
+            self.repeat_prompt = None;
+        }
+        if self
+            .status_prompt
+            .as_ref()
+            .map(|(approved, _)| approved.media_id)
+            != media_id
+        {
+            self.status_prompt = None;
+        }
         self.current = media_list;
         self.recognized = recognized;
     }
@@ -115,11 +480,30 @@ impl Default for CurrentMediaPage {
             Resources::get("cover_default.jpg").expect("failed to load default media cover");
         CurrentMediaPage {
             update_cancel_btn_state: button::State::default(),
+            choose_media_btn_state: button::State::default(),
             show_cancel_update: false,
             current: None,
             recognized: None,
             cover: None,
             default_cover: image::Handle::from_memory(Vec::from(&*image)),
+            match_choices: Vec::new(),
+            match_choice_states: Vec::new(),
+            match_dismiss_state: button::State::default(),
+            watch_progress: None,
+            repeat_prompt: None,
+            repeat_yes_state: button::State::default(),
+            repeat_no_state: button::State::default(),
+            status_prompt: None,
+            status_yes_state: button::State::default(),
+            status_no_state: button::State::default(),
+            add_prompt: None,
+            add_yes_state: button::State::default(),
+            add_no_state: button::State::default(),
+            pending_update: None,
+            accept_state: button::State::default(),
+            decrement_state: button::State::default(),
+            increment_state: button::State::default(),
+            reject_state: button::State::default(),
         }
     }
 }
@@ -151,6 +535,9 @@ impl Event for MediaChange {
             debug!("hiding list update cancel button");
         }
         app.page.current_media.show_cancel_button(needs_update);
+        if !needs_update {
+            app.page.current_media.set_watch_progress(None);
+        }
         if media_list.is_none() {
             app.page.current_media.set_media_cover(None);
         }
@@ -167,7 +554,15 @@ pub struct CancelListUpdate(pub i32, pub bool);
 impl Event for CancelListUpdate {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
         let CancelListUpdate(media_id, already_sent) = self;
-        app.page.current_media.show_cancel_button(false);
+        let is_current = match &app.media {
+            Some(media) => media.media_id == media_id,
+            None => false,
+        };
+        if is_current {
+            app.page.current_media.show_cancel_button(false);
+            app.page.current_media.set_watch_progress(None);
+            app.page.current_media.set_status_prompt(None);
+        }
         if !already_sent {
             let index = app.updates.find_index(media_id);
             match index {
@@ -181,3 +576,190 @@ impl Event for CancelListUpdate {
         None
     }
 }
+
+/// The user accepted the detected update of the media, it's sent once it's been watched enough
+#[derive(Debug, Clone)]
+pub struct AcceptUpdate(pub i32);
+
+impl Event for AcceptUpdate {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let AcceptUpdate(media_id) = self;
+        if app.updates.approve(media_id) {
+            debug!("update of media {} accepted", media_id);
+        } else {
+            warn!("could not find media_id {} in list update queue", media_id);
+        }
+        None
+    }
+}
+
+/// Changes the progress of the queued update of the media by the amount
+#[derive(Debug, Clone)]
+pub struct EditUpdate(pub i32, pub i32);
+
+impl Event for EditUpdate {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let EditUpdate(media_id, change) = self;
+        let update = app.updates.get(media_id)?;
+        let list = match update.media.as_ref()?.media_type? {
+            anilist::MediaType::Anime => app.page.anime.get_list(),
+            anilist::MediaType::Manga => app.page.manga.get_list(),
+        }?;
+        let rules = settings::get_settings().read().unwrap().status_rules;
+        let (edited, result) = list
+            .find_entry_by_id(media_id)?
+            .edit_update(update, change, &rules);
+        app.updates.replace(edited.clone());
+        // A status change asked about before may not fit the edited progress
+        let prompt = result.pending.map(|change| {
+            let mut approved = edited;
+            approved.apply_status_change(change, &rules);
+            (approved, change)
+        });
+        app.page.current_media.set_status_prompt(prompt);
+        None
+    }
+}
+
+/// Searches again to let the user pick what the recognized media is
+#[derive(Debug, Clone)]
+pub struct ChooseMedia;
+
+impl Event for ChooseMedia {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let recognized = app.recognized.clone()?;
+        let token = settings::get_settings()
+            .read()
+            .unwrap()
+            .anilist
+            .token()
+            .clone()?;
+        Some(App::query_search(token, recognized, false, true))
+    }
+}
+
+/// The user picked which search result the recognized media is, or None if it was none of them.
+/// The choice is remembered so the title isn't searched for again.
+#[derive(Debug, Clone)]
+pub struct MatchChosen(pub Option<i32>);
+
+impl Event for MatchChosen {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let MatchChosen(media_id) = self;
+        app.page.current_media.set_match_choices(Vec::new());
+        let media_id = media_id?;
+        let recognized = app.recognized.clone()?;
+        let key = recognition::normalize_title(&recognized.title).alias_key();
+        {
+            let mut settings = settings::get_settings().write().unwrap();
+            settings.aliases.set(key, recognized.media_type, media_id);
+            if let Err(err) = settings.aliases.save() {
+                warn!("could not save title aliases: {}", err);
+            }
+        }
+        app.media_matched(media_id, recognized)
+    }
+}
+
+/// Asks whether a completed media is being watched or read again
+#[derive(Debug, Clone)]
+pub struct AskRepeat(pub anilist::MediaList);
+
+impl Event for AskRepeat {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let AskRepeat(repeat) = self;
+        app.page.current_media.set_repeat_prompt(Some(repeat));
+        None
+    }
+}
+
+/// The user said whether the completed media is being repeated
+#[derive(Debug, Clone)]
+pub struct RepeatChosen(pub bool);
+
+impl Event for RepeatChosen {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let RepeatChosen(repeating) = self;
+        let repeat = app.page.current_media.take_repeat_prompt()?;
+        if !repeating {
+            return None;
+        }
+        let recognized = app.recognized.clone()?;
+        let policy = settings::get_settings()
+            .read()
+            .unwrap()
+            .watch_policy
+            .get(recognized.media_type);
+        debug!("repeating media {}", repeat.media_id);
+        app.media = Some(repeat.clone());
+        app.updates
+            .enqueue_detected(repeat.clone(), recognized.playback, policy);
+        app.page
+            .current_media
+            .set_watch_progress(app.updates.progress(repeat.media_id));
+        Some(forward_message(
+            MediaChange(Some(repeat), Some(recognized), true).into(),
+        ))
+    }
+}
+
+/// Asks whether to make a status change the rules want approved,
+/// the list entry is what the update becomes if it's approved
+#[derive(Debug, Clone)]
+pub struct AskStatusChange(pub anilist::MediaList, pub anilist::StatusChange);
+
+impl Event for AskStatusChange {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let AskStatusChange(approved, change) = self;
+        app.page
+            .current_media
+            .set_status_prompt(Some((approved, change)));
+        None
+    }
+}
+
+/// The user said whether to make the status change
+#[derive(Debug, Clone)]
+pub struct StatusChangeChosen(pub bool);
+
+impl Event for StatusChangeChosen {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let StatusChangeChosen(approve) = self;
+        let (approved, change) = app.page.current_media.take_status_prompt()?;
+        if !approve {
+            return None;
+        }
+        debug!("{:?} approved for media {}", change, approved.media_id);
+        // The progress update may have been sent already, the status change still needs to be
+        if !app.updates.replace(approved.clone()) {
+            app.updates.enqueue(approved.clone());
+        }
+        app.media = Some(approved.clone());
+        Some(forward_message(
+            MediaChange(Some(approved), app.recognized.clone(), true).into(),
+        ))
+    }
+}
+
+/// The user said whether to add the offered media to their list
+#[derive(Debug, Clone)]
+pub struct AddChosen(pub bool);
+
+impl Event for AddChosen {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let AddChosen(add) = self;
+        let candidate = app.page.current_media.take_add_prompt()?;
+        if !add {
+            return None;
+        }
+        let recognized = app.recognized.clone()?;
+        let token = settings::get_settings()
+            .read()
+            .unwrap()
+            .anilist
+            .token()
+            .clone()?;
+        debug!("adding media {} to the list", candidate.media_id);
+        Some(App::add_to_list(token, candidate.media_id, recognized))
+    }
+}
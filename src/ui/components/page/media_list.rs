@@ -66,6 +66,15 @@ impl MediaListPage {
         }
     }
 
+    /// Adds an entry to the list, like one created for detected media
+    pub fn insert_entry(&mut self, entry: anilist::MediaList) {
+        let mut list = self.list.take();
+        if let Some(list) = &mut list {
+            list.insert_entry(entry, self.media_type);
+        }
+        self.set_list(list);
+    }
+
     pub fn get_list_mut(&mut self) -> Option<&mut anilist::MediaListCollection> {
         self.list.as_mut()
     }
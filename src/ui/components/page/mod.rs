@@ -1,5 +1,9 @@
-use crate::{anilist::MediaType, app::Message, ui::style};
-use iced::{Container, Element, Length, Row};
+use crate::{
+    anilist::MediaType,
+    app::{App, Event, Message},
+    ui::style,
+};
+use iced::{button, Button, Column, Command, Container, Element, Length, Row, Text};
 
 mod settings;
 pub use settings::*;
@@ -10,11 +14,15 @@ pub use current_media::*;
 mod media_list;
 pub use media_list::*;
 
+mod updates;
+pub use updates::*;
+
 #[derive(Debug, Clone)]
 pub enum Page {
     Anime,
     Manga,
     CurrentMedia,
+    Updates,
     Settings,
 }
 
@@ -28,23 +36,60 @@ impl Default for Page {
 pub struct PageContainer {
     pub page: Page,
     pub current_media: CurrentMediaPage,
+    pub updates: UpdatesPage,
     pub settings: SettingsPage,
     pub anime: MediaListPage,
     pub manga: MediaListPage,
+    /// The last request that failed, shown above every page until dismissed
+    error: Option<String>,
+    dismiss_state: button::State,
 }
 
 impl PageContainer {
     pub fn update(&mut self, _msg: Message) {}
 
     pub fn view(&mut self) -> Element<Message> {
-        match self.page {
+        let page = match self.page {
             Page::CurrentMedia => self.current_media.view(),
+            Page::Updates => self.updates.view(),
             Page::Settings => self.settings.view(),
             Page::Anime => self.anime.view(),
             Page::Manga => self.manga.view(),
+        };
+        match &self.error {
+            Some(error) => {
+                let banner = Row::new()
+                    .spacing(12)
+                    .push(
+                        Text::new(error.as_str())
+                            .size(14)
+                            .width(Length::Fill)
+                            .color(style::danger_text()),
+                    )
+                    .push(
+                        Button::new(&mut self.dismiss_state, Text::new("Dismiss").size(14))
+                            .padding(6)
+                            .style(style::Button::Danger)
+                            .on_press(DismissError.into()),
+                    );
+                Column::new()
+                    .push(
+                        Container::new(banner)
+                            .width(Length::Fill)
+                            .padding(12)
+                            .style(style::Container::Background),
+                    )
+                    .push(page)
+                    .into()
+            }
+            None => page,
         }
     }
 
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
     pub fn change_page(&mut self, page: Page) {
         self.page = page;
     }
@@ -63,9 +108,23 @@ impl Default for PageContainer {
         PageContainer {
             page: Page::default(),
             current_media: CurrentMediaPage::default(),
+            updates: UpdatesPage::default(),
             settings: SettingsPage::default(),
             anime: MediaListPage::new(MediaType::Anime),
             manga: MediaListPage::new(MediaType::Manga),
+            error: None,
+            dismiss_state: button::State::default(),
         }
     }
 }
+
+/// Hides the error of the last failed request
+#[derive(Debug, Clone)]
+pub struct DismissError;
+
+impl Event for DismissError {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        app.page.error = None;
+        None
+    }
+}
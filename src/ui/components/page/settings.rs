@@ -1,11 +1,15 @@
 use super::PageContainer;
 use crate::{
-    app::{App, Event, Message},
+    anilist::{
+        self, AuthFlow, AutoAddMode, MediaType, StatusChange, StatusRules, TransitionMode,
+        UnknownTotal, UpdateMode,
+    },
+    app::{forward_message, App, Authorized, Event, Message, NoMessage},
     ui::style,
 };
 use iced::{
-    button, text_input, Button, Column, Command, Container, Element, HorizontalAlignment, Length,
-    Text, TextInput, VerticalAlignment,
+    button, text_input, Button, Checkbox, Column, Command, Container, Element, HorizontalAlignment,
+    Length, Radio, Text, TextInput, VerticalAlignment,
 };
 use log::warn;
 
@@ -14,8 +18,19 @@ pub struct SettingsPage {
     pub logged_in: bool,
     refresh_list_state: button::State,
     login_state: button::State,
+    manual_url_state: text_input::State,
+    manual_url: String,
+    token_state: text_input::State,
+    token_value: String,
+    save_token_state: button::State,
     update_delay_state: text_input::State,
     update_delay_value: String,
+    watched_percentage_state: text_input::State,
+    watched_percentage_value: String,
+    anime_detection_state: text_input::State,
+    anime_detection_value: String,
+    manga_detection_state: text_input::State,
+    manga_detection_value: String,
 }
 
 impl SettingsPage {
@@ -44,52 +59,290 @@ impl SettingsPage {
                     Logout.into(),
                 ));
         } else {
-            anilist_inner = anilist_inner.push(Self::button(
+            let auth_flow = crate::settings::get_settings().read().unwrap().auth_flow;
+            let mut flows = Column::new().spacing(6).push(
+                Text::new("Log in")
+                    .size(16)
+                    .horizontal_alignment(HorizontalAlignment::Left),
+            );
+            let options = [
+                (AuthFlow::Code, "Through the Fubuki login server"),
+                (AuthFlow::Implicit, "Directly with AniList"),
+                (AuthFlow::Manual, "By pasting a token"),
+            ];
+            for &(flow, label) in options.iter() {
+                flows = flows.push(Radio::new(flow, label, Some(auth_flow), |flow| {
+                    SettingChange::AuthFlow(flow).into()
+                }));
+            }
+            let login_label = if auth_flow == AuthFlow::Manual {
+                "Open Login Page"
+            } else {
+                "Login"
+            };
+            anilist_inner = anilist_inner.push(flows).push(Self::button(
                 &mut self.login_state,
-                "Login",
+                login_label,
                 style::Button::Accent,
                 Login.into(),
             ));
+            if auth_flow == AuthFlow::Manual {
+                self.manual_url = anilist::manual_login_url().unwrap_or_default();
+                anilist_inner = anilist_inner
+                    .push(
+                        Text::new(
+                            "Log in at this address, then paste the token or the whole address \
+                             the login ends up on",
+                        )
+                        .size(14),
+                    )
+                    .push(
+                        TextInput::new(&mut self.manual_url_state, "", &self.manual_url, |_| {
+                            NoMessage.into()
+                        })
+                        .style(style::Input)
+                        .padding(input_padding)
+                        .size(14),
+                    )
+                    .push(
+                        TextInput::new(
+                            &mut self.token_state,
+                            "Token",
+                            &self.token_value,
+                            |value| TokenInputChange(value).into(),
+                        )
+                        .style(style::Input)
+                        .padding(input_padding)
+                        .size(14)
+                        .on_submit(SubmitToken.into()),
+                    )
+                    .push(Self::button(
+                        &mut self.save_token_state,
+                        "Save Token",
+                        style::Button::Accent,
+                        SubmitToken.into(),
+                    ));
+            }
         }
         col = col.push(Self::container(anilist_inner.into()));
 
         let general_inner = Column::new().spacing(12);
-        let mut update_delay = Column::new().spacing(12);
-        self.update_delay_value = format!(
-            "{}",
-            crate::settings::get_settings().read().unwrap().update_delay
+        let ignore_paused;
+        let rules;
+        let auto_add;
+        let update_mode;
+        {
+            let settings = crate::settings::get_settings().read().unwrap();
+            let policy = settings.watch_policy;
+            self.update_delay_value = format!("{}", settings.update_delay);
+            self.watched_percentage_value = format!("{}", policy.anime.min_playback_percentage);
+            self.anime_detection_value = format!("{}", policy.anime.min_detection_secs);
+            self.manga_detection_value = format!("{}", policy.manga.min_detection_secs);
+            ignore_paused = policy.anime.ignore_paused;
+            rules = settings.status_rules;
+            auto_add = settings.auto_add.mode;
+            update_mode = settings.update_mode;
+        }
+
+        let update_delay = Self::text_setting(
+            &mut self.update_delay_state,
+            "List update delay (seconds)",
+            &self.update_delay_value,
+            |value, save| SettingChange::UpdateDelay(value, save).into(),
+        );
+        let watched_percentage = Self::text_setting(
+            &mut self.watched_percentage_state,
+            "Watched percentage before updating",
+            &self.watched_percentage_value,
+            |value, save| SettingChange::WatchedPercentage(value, save).into(),
+        );
+        let anime_detection = Self::text_setting(
+            &mut self.anime_detection_state,
+            "Seconds an anime is detected before updating",
+            &self.anime_detection_value,
+            |value, save| SettingChange::DetectionTime(MediaType::Anime, value, save).into(),
+        );
+        let manga_detection = Self::text_setting(
+            &mut self.manga_detection_state,
+            "Seconds a manga is detected before updating",
+            &self.manga_detection_value,
+            |value, save| SettingChange::DetectionTime(MediaType::Manga, value, save).into(),
+        );
+        let ignore_paused = Checkbox::new(
+            ignore_paused,
+            "Don't count time paused as watching",
+            |ignore| SettingChange::IgnorePaused(ignore).into(),
         );
 
-        update_delay = update_delay
-            .push(
-                Text::new("List update delay (seconds)")
-                    .size(16)
-                    .horizontal_alignment(HorizontalAlignment::Left)
-                    .vertical_alignment(VerticalAlignment::Center),
-            )
-            .push(
-                TextInput::new(
-                    &mut self.update_delay_state,
-                    "",
-                    &self.update_delay_value,
-                    |value| SettingChange::UpdateDelay(value, false).into(),
-                )
-                .style(style::Input)
-                .padding(input_padding)
-                .width(Length::Units(80))
+        let mut update_modes = Column::new().spacing(6).push(
+            Text::new("Detected updates")
                 .size(16)
-                .on_submit(
-                    SettingChange::UpdateDelay(self.update_delay_value.clone(), true).into(),
-                ),
-            );
+                .horizontal_alignment(HorizontalAlignment::Left),
+        );
+        let modes = [
+            (UpdateMode::Automatic, "Send automatically"),
+            (UpdateMode::Confirm, "Wait for me to accept them"),
+            (UpdateMode::NotifyOnly, "Only show them, never send"),
+        ];
+        for &(mode, label) in modes.iter() {
+            update_modes = update_modes.push(Radio::new(mode, label, Some(update_mode), |mode| {
+                SettingChange::UpdateMode(mode).into()
+            }));
+        }
+        let offer_add = Checkbox::new(
+            auto_add != AutoAddMode::Off,
+            "Offer to add detected media that isn't in your list",
+            |offer| {
+                let mode = if offer {
+                    AutoAddMode::Ask
+                } else {
+                    AutoAddMode::Off
+                };
+                SettingChange::AutoAdd(mode).into()
+            },
+        );
+        let auto_add = Checkbox::new(
+            auto_add == AutoAddMode::Automatic,
+            "Add close matches without asking",
+            |automatic| {
+                let mode = if automatic {
+                    AutoAddMode::Automatic
+                } else {
+                    AutoAddMode::Ask
+                };
+                SettingChange::AutoAdd(mode).into()
+            },
+        );
 
         col = col
             .push(Self::header_title("General"))
-            .push(Self::container(general_inner.push(update_delay).into()));
+            .push(Self::container(
+                general_inner
+                    .push(update_delay)
+                    .push(update_modes)
+                    .push(anime_detection)
+                    .push(watched_percentage)
+                    .push(ignore_paused)
+                    .push(manga_detection)
+                    .push(offer_add)
+                    .push(auto_add)
+                    .into(),
+            ));
+
+        col = col
+            .push(Self::header_title("Status Changes"))
+            .push(Self::container(Self::status_rules(rules)));
+
+        let mut recognition_inner = Column::new().spacing(6);
+        let rule_errors = crate::recognition::rule_errors();
+        if rule_errors.is_empty() {
+            recognition_inner = recognition_inner.push(Text::new("All rules loaded").size(14));
+        } else {
+            recognition_inner = recognition_inner.push(
+                Text::new(
+                    "Some custom rules could not be loaded, the last working rules are in use",
+                )
+                .size(14)
+                .color(style::danger_text()),
+            );
+            for err in rule_errors {
+                recognition_inner = recognition_inner.push(Text::new(err).size(14));
+            }
+        }
+        col = col
+            .push(Self::header_title("Recognition"))
+            .push(Self::container(recognition_inner.into()));
 
         PageContainer::container(col.into()).into()
     }
 
+    fn status_rules<'a>(rules: StatusRules) -> Element<'a, Message> {
+        let transition = |mode: TransitionMode, change: StatusChange, label: &str, ask: &str| {
+            Column::new()
+                .spacing(6)
+                .push(Checkbox::new(
+                    mode != TransitionMode::Never,
+                    label,
+                    move |enabled| {
+                        let mode = if enabled {
+                            TransitionMode::Automatic
+                        } else {
+                            TransitionMode::Never
+                        };
+                        SettingChange::Transition(change, mode).into()
+                    },
+                ))
+                .push(Checkbox::new(
+                    mode == TransitionMode::Confirm,
+                    ask,
+                    move |confirm| {
+                        let mode = if confirm {
+                            TransitionMode::Confirm
+                        } else {
+                            TransitionMode::Automatic
+                        };
+                        SettingChange::Transition(change, mode).into()
+                    },
+                ))
+        };
+        Column::new()
+            .spacing(12)
+            .push(transition(
+                rules.start,
+                StatusChange::Start,
+                "Mark planned, paused and dropped media as current when progress is made",
+                "Ask first",
+            ))
+            .push(Checkbox::new(
+                rules.start_at_any_progress,
+                "Also after the first episode or chapter, like when resuming paused media",
+                |any| SettingChange::StartAtAnyProgress(any).into(),
+            ))
+            .push(transition(
+                rules.complete,
+                StatusChange::Complete,
+                "Mark media as completed at the last episode or chapter",
+                "Ask first",
+            ))
+            .push(Checkbox::new(
+                rules.unknown_total == UnknownTotal::ConfirmWhenFinished,
+                "Ask whether finished media without an episode or chapter count is completed",
+                |ask| SettingChange::UnknownTotal(ask).into(),
+            ))
+            .push(Checkbox::new(
+                rules.overwrite_dates,
+                "Replace start and completion dates that are already set",
+                |overwrite| SettingChange::OverwriteDates(overwrite).into(),
+            ))
+            .into()
+    }
+
+    fn text_setting<'a>(
+        state: &'a mut text_input::State,
+        label: &str,
+        value: &str,
+        msg: fn(String, bool) -> Message,
+    ) -> Element<'a, Message> {
+        let input_padding = 6;
+        Column::new()
+            .spacing(12)
+            .push(
+                Text::new(label)
+                    .size(16)
+                    .horizontal_alignment(HorizontalAlignment::Left)
+                    .vertical_alignment(VerticalAlignment::Center),
+            )
+            .push(
+                TextInput::new(state, "", value, move |value| msg(value, false))
+                    .style(style::Input)
+                    .padding(input_padding)
+                    .width(Length::Units(80))
+                    .size(16)
+                    .on_submit(msg(value.to_string(), true)),
+            )
+            .into()
+    }
+
     fn container(element: Element<Message>) -> Element<Message> {
         Container::new(element).padding(12).into()
     }
@@ -162,11 +415,56 @@ pub struct Login;
 
 impl Event for Login {
     fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let flow = crate::settings::get_settings().read().unwrap().auth_flow;
+        if flow == AuthFlow::Manual {
+            // The token is pasted in once the user has it
+            match anilist::manual_login_url() {
+                Ok(url) => {
+                    if let Err(err) = open::that(url) {
+                        warn!("could not open the login page: {}", err);
+                    }
+                }
+                Err(err) => warn!("could not make the login url: {}", err),
+            }
+            return None;
+        }
         app.page.settings.logged_in = true;
         if app.user.is_some() {
             None
         } else {
-            Some(App::auth())
+            Some(App::auth(flow))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenInputChange(pub String);
+
+impl Event for TokenInputChange {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let TokenInputChange(value) = self;
+        app.page.settings.token_value = value;
+        None
+    }
+}
+
+/// Logs in with the pasted token
+#[derive(Debug, Clone)]
+pub struct SubmitToken;
+
+impl Event for SubmitToken {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        match anilist::token_from_input(&app.page.settings.token_value) {
+            Some(token) => {
+                app.page.settings.token_value.clear();
+                app.page.settings.logged_in = true;
+                Some(forward_message(Authorized(token).into()))
+            }
+            None => {
+                app.page
+                    .set_error("That doesn't look like an AniList token".to_string());
+                None
+            }
         }
     }
 }
@@ -174,6 +472,16 @@ impl Event for Login {
 #[derive(Debug, Clone)]
 pub enum SettingChange {
     UpdateDelay(String, bool),
+    WatchedPercentage(String, bool),
+    DetectionTime(MediaType, String, bool),
+    IgnorePaused(bool),
+    Transition(StatusChange, TransitionMode),
+    StartAtAnyProgress(bool),
+    UnknownTotal(bool),
+    OverwriteDates(bool),
+    AutoAdd(AutoAddMode),
+    UpdateMode(UpdateMode),
+    AuthFlow(AuthFlow),
 }
 
 impl Event for SettingChange {
@@ -188,6 +496,63 @@ impl Event for SettingChange {
                 }
                 Err(err) => warn!("could not parse new update delay: {}", err),
             },
+            SettingChange::WatchedPercentage(percentage, save) => match percentage.parse::<f64>() {
+                Ok(percentage) if (0.0..=100.0).contains(&percentage) => {
+                    settings.watch_policy.anime.min_playback_percentage = percentage;
+                    changed = save;
+                }
+                Ok(percentage) => warn!("watched percentage {} is out of range", percentage),
+                Err(err) => warn!("could not parse new watched percentage: {}", err),
+            },
+            SettingChange::DetectionTime(media_type, secs, save) => match secs.parse::<u64>() {
+                Ok(secs) => {
+                    match media_type {
+                        MediaType::Anime => settings.watch_policy.anime.min_detection_secs = secs,
+                        MediaType::Manga => settings.watch_policy.manga.min_detection_secs = secs,
+                    }
+                    changed = save;
+                }
+                Err(err) => warn!("could not parse new detection time: {}", err),
+            },
+            SettingChange::IgnorePaused(ignore) => {
+                settings.watch_policy.anime.ignore_paused = ignore;
+                changed = true;
+            }
+            SettingChange::Transition(change, mode) => {
+                match change {
+                    StatusChange::Start => settings.status_rules.start = mode,
+                    StatusChange::Complete => settings.status_rules.complete = mode,
+                }
+                changed = true;
+            }
+            SettingChange::StartAtAnyProgress(any) => {
+                settings.status_rules.start_at_any_progress = any;
+                changed = true;
+            }
+            SettingChange::UnknownTotal(ask) => {
+                settings.status_rules.unknown_total = if ask {
+                    UnknownTotal::ConfirmWhenFinished
+                } else {
+                    UnknownTotal::Ignore
+                };
+                changed = true;
+            }
+            SettingChange::OverwriteDates(overwrite) => {
+                settings.status_rules.overwrite_dates = overwrite;
+                changed = true;
+            }
+            SettingChange::UpdateMode(mode) => {
+                settings.update_mode = mode;
+                changed = true;
+            }
+            SettingChange::AutoAdd(mode) => {
+                settings.auto_add.mode = mode;
+                changed = true;
+            }
+            SettingChange::AuthFlow(flow) => {
+                settings.auth_flow = flow;
+                changed = true;
+            }
         }
         if changed {
             if let Err(err) = settings.save() {
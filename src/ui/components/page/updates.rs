@@ -0,0 +1,305 @@
+use super::{CancelListUpdate, EditUpdate, PageContainer};
+use crate::{
+    anilist,
+    app::{App, Event, Message},
+    ui::style,
+};
+use iced::{
+    button, scrollable, Button, Column, Command, Container, Element, HorizontalAlignment, Length,
+    Row, Scrollable, Text,
+};
+use log::{debug, warn};
+
+/// The buttons of one queued update
+#[derive(Debug, Clone, Default)]
+struct QueuedButtons {
+    send: button::State,
+    decrement: button::State,
+    increment: button::State,
+    discard: button::State,
+}
+
+/// Every queued list update, the ones that failed and the ones sent recently
+#[derive(Debug, Clone, Default)]
+pub struct UpdatesPage {
+    queued: Vec<(anilist::QueuedInfo, Vec<anilist::FieldChange>)>,
+    history: Vec<anilist::SentUpdate>,
+    failed: Vec<anilist::FailedUpdate>,
+    button_states: Vec<QueuedButtons>,
+    failed_button_states: Vec<(button::State, button::State)>,
+    scroll_state: scrollable::State,
+}
+
+impl UpdatesPage {
+    pub fn view(&mut self) -> Element<Message> {
+        let title_size = 18;
+        let text_size = 14;
+        let mut col = Column::new()
+            .spacing(12)
+            .push(Text::new("Queued Updates").size(title_size));
+        if self.queued.is_empty() {
+            col = col.push(Text::new("Nothing is waiting to be sent").size(text_size));
+        }
+        for ((info, changes), states) in self.queued.iter().zip(self.button_states.iter_mut()) {
+            col = col.push(Self::queued_view(info, changes, states));
+        }
+
+        if !self.failed.is_empty() {
+            col = col.push(Text::new("Failed Updates").size(title_size));
+        }
+        for (failed, (retry, discard)) in
+            self.failed.iter().zip(self.failed_button_states.iter_mut())
+        {
+            col = col.push(Self::failed_view(failed, retry, discard));
+        }
+
+        col = col.push(Text::new("Recently Sent").size(title_size));
+        if self.history.is_empty() {
+            col = col.push(Text::new("No updates sent yet").size(text_size));
+        }
+        for sent in self.history.iter() {
+            col = col.push(Self::sent_view(sent));
+        }
+
+        let scroll = Scrollable::new(&mut self.scroll_state)
+            .width(Length::Fill)
+            .push(col);
+        PageContainer::container(scroll.into()).into()
+    }
+
+    fn queued_view<'a>(
+        info: &anilist::QueuedInfo,
+        changes: &[anilist::FieldChange],
+        states: &'a mut QueuedButtons,
+    ) -> Element<'a, Message> {
+        let text_size = 14;
+        let button_padding = 8;
+        let media_id = info.media.media_id;
+        let button = |state: &'a mut button::State, label: &str, btn_style, msg: Message| {
+            Button::new(
+                state,
+                Text::new(label)
+                    .size(text_size)
+                    .horizontal_alignment(HorizontalAlignment::Center),
+            )
+            .padding(button_padding)
+            .style(btn_style)
+            .on_press(msg)
+        };
+        let col = Column::new()
+            .spacing(6)
+            .push(Text::new(Self::title(&info.media)).size(16))
+            .push(Text::new(Self::state_text(&info.state)).size(text_size))
+            .push(Text::new(Self::changes_text(changes)).size(text_size))
+            .push(
+                Row::new()
+                    .spacing(6)
+                    .push(button(
+                        &mut states.send,
+                        "Send Now",
+                        style::Button::Accent,
+                        SendUpdateNow(media_id).into(),
+                    ))
+                    .push(button(
+                        &mut states.decrement,
+                        "-",
+                        style::Button::Accent,
+                        EditUpdate(media_id, -1).into(),
+                    ))
+                    .push(button(
+                        &mut states.increment,
+                        "+",
+                        style::Button::Accent,
+                        EditUpdate(media_id, 1).into(),
+                    ))
+                    .push(button(
+                        &mut states.discard,
+                        "Discard",
+                        style::Button::Danger,
+                        CancelListUpdate(media_id, false).into(),
+                    )),
+            );
+        Self::entry_container(col)
+    }
+
+    fn failed_view<'a>(
+        failed: &anilist::FailedUpdate,
+        retry_state: &'a mut button::State,
+        discard_state: &'a mut button::State,
+    ) -> Element<'a, Message> {
+        let text_size = 14;
+        let button = |state: &'a mut button::State, label: &str, btn_style, msg: Message| {
+            Button::new(
+                state,
+                Text::new(label)
+                    .size(text_size)
+                    .horizontal_alignment(HorizontalAlignment::Center),
+            )
+            .padding(8)
+            .style(btn_style)
+            .on_press(msg)
+        };
+        let media_id = failed.media.media_id;
+        Self::entry_container(
+            Column::new()
+                .spacing(6)
+                .push(Text::new(Self::title(&failed.media)).size(16))
+                .push(
+                    Text::new(format!(
+                        "Failed after {} tries: {}",
+                        failed.attempts, failed.error
+                    ))
+                    .size(text_size)
+                    .color(style::danger_text()),
+                )
+                .push(
+                    Row::new()
+                        .spacing(6)
+                        .push(button(
+                            retry_state,
+                            "Retry",
+                            style::Button::Accent,
+                            RetryFailed(media_id).into(),
+                        ))
+                        .push(button(
+                            discard_state,
+                            "Discard",
+                            style::Button::Danger,
+                            DiscardFailed(media_id).into(),
+                        )),
+                ),
+        )
+    }
+
+    fn sent_view<'a>(sent: &anilist::SentUpdate) -> Element<'a, Message> {
+        let text_size = 14;
+        let result = match &sent.result {
+            Some(Ok(())) => Text::new("Sent").size(text_size),
+            Some(Err(err)) => Text::new(format!("Failed: {}", err))
+                .size(text_size)
+                .color(style::danger_text()),
+            None => Text::new("Sending...").size(text_size),
+        };
+        Self::entry_container(
+            Column::new()
+                .spacing(6)
+                .push(
+                    Text::new(format!("{} {}", sent.sent_at.format("%H:%M"), sent.title)).size(16),
+                )
+                .push(Text::new(Self::changes_text(&sent.changes)).size(text_size))
+                .push(result),
+        )
+    }
+
+    fn entry_container(col: Column<Message>) -> Element<Message> {
+        Container::new(col)
+            .width(Length::Fill)
+            .padding(12)
+            .style(style::Container::EntryRow)
+            .into()
+    }
+
+    fn title(media: &anilist::MediaList) -> String {
+        media
+            .media
+            .as_ref()
+            .and_then(|media| media.preferred_title())
+            .unwrap_or_else(|| format!("Media {}", media.media_id))
+    }
+
+    fn state_text(state: &anilist::QueuedState) -> String {
+        match state {
+            anilist::QueuedState::Delayed(left) => format!("Sending in {}s", left.as_secs() + 1),
+            anilist::QueuedState::Watching(progress) => {
+                let detected = progress.detected.as_secs();
+                let required = progress.required.as_secs();
+                format!(
+                    "Waiting until it's watched enough, {}:{:02} of {}:{:02}",
+                    detected / 60,
+                    detected % 60,
+                    required / 60,
+                    required % 60
+                )
+            }
+            anilist::QueuedState::NeedsApproval => "Waiting for you to accept it".to_string(),
+            anilist::QueuedState::NotSent => "Not sent, updates are only shown".to_string(),
+            anilist::QueuedState::Restored => {
+                "Left from last time, waiting for the list to load".to_string()
+            }
+            anilist::QueuedState::Retrying(left, attempts) => format!(
+                "Failed {} times, trying again in {}s",
+                attempts,
+                left.as_secs() + 1
+            ),
+            anilist::QueuedState::Ready => "Sending".to_string(),
+        }
+    }
+
+    fn changes_text(changes: &[anilist::FieldChange]) -> String {
+        if changes.is_empty() {
+            return "Nothing changes".to_string();
+        }
+        changes
+            .iter()
+            .map(|change| format!("{}: {} -> {}", change.field, change.before, change.after))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn set_updates(
+        &mut self,
+        queued: Vec<(anilist::QueuedInfo, Vec<anilist::FieldChange>)>,
+        history: Vec<anilist::SentUpdate>,
+        failed: Vec<anilist::FailedUpdate>,
+    ) {
+        self.button_states
+            .resize(queued.len(), QueuedButtons::default());
+        self.failed_button_states
+            .resize(failed.len(), Default::default());
+        self.queued = queued;
+        self.history = history;
+        self.failed = failed;
+    }
+}
+
+/// Sends the queued update of the media without waiting for it
+#[derive(Debug, Clone)]
+pub struct SendUpdateNow(pub i32);
+
+impl Event for SendUpdateNow {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let SendUpdateNow(media_id) = self;
+        if app.updates.send_now(media_id) {
+            debug!("sending update of media {} now", media_id);
+        } else {
+            warn!("could not find media_id {} in list update queue", media_id);
+        }
+        None
+    }
+}
+
+/// Queues a failed update of the media again
+#[derive(Debug, Clone)]
+pub struct RetryFailed(pub i32);
+
+impl Event for RetryFailed {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let RetryFailed(media_id) = self;
+        if !app.updates.retry_failed(media_id) {
+            warn!("no failed update for media_id {}", media_id);
+        }
+        None
+    }
+}
+
+/// Forgets a failed update of the media
+#[derive(Debug, Clone)]
+pub struct DiscardFailed(pub i32);
+
+impl Event for DiscardFailed {
+    fn handle(self, app: &mut App) -> Option<Command<Message>> {
+        let DiscardFailed(media_id) = self;
+        app.updates.discard_failed(media_id);
+        None
+    }
+}
@@ -2,6 +2,11 @@ use iced::{button, container, text_input, Background, Color};
 
 // const CONTAINER_BACKGROUND: Color = Color::from_rgb8(11u8, 22u8, 34u8);
 
+/// Text color for errors, matches the danger button
+pub fn danger_text() -> Color {
+    Color::from_rgb8(189u8, 80u8, 102u8)
+}
+
 pub enum Button {
     Nav { selected: bool },
     Accent,
@@ -1,10 +1,13 @@
+use crate::anilist;
 use anyhow::Result;
 use iced::image;
 
 pub async fn fetch_image(url: String) -> Result<image::Handle> {
     #[cfg(not(target_arch = "wasm32"))]
     {
-        let bytes = reqwest::get(&url).await?.bytes().await?;
+        // Images aren't API requests, they only wait for the ones the user is waiting on
+        anilist::LIMITER.yield_to_interactive().await;
+        let bytes = anilist::client().get(&url).send().await?.bytes().await?;
 
         Ok(image::Handle::from_memory(bytes.as_ref().to_vec()))
     }